@@ -1,7 +1,37 @@
+use crate::eco;
+use crate::engine::{self, Limits};
+use crate::explain;
+use crate::i18n::{self, Text};
+use crate::polyglot;
 use crate::models::*;
-use shakmaty::{Chess, Position, Square, fen::Epd, san::San};
-
-/// Analyze a PGN game and produce move-by-move analysis
+use crate::see;
+use crate::tactics;
+use crate::threats;
+use shakmaty::{Chess, Position, Role, Square, fen::Epd, san::{San, SanPlus}};
+
+/// Drop in winning chances that makes an Inaccuracy, Mistake or Blunder
+const INACCURACY: f64 = 0.1;
+const MISTAKE: f64 = 0.2;
+const BLUNDER: f64 = 0.3;
+/// Moves within this many centipawns of the engine's choice count as best
+const BEST_MARGIN: i32 = 10;
+/// Material (centipawns) a move must give up to count as a sacrifice
+const SACRIFICE: i32 = 200;
+
+/// Engine lines kept per ply, and how much of each line is shown
+const CANDIDATES: usize = 3;
+const CANDIDATE_PV_PLIES: usize = 6;
+/// Version of the saved analysis. Bump it when classification or the stored
+/// fields change, so cached analyses of an older version are redone.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Longest forced mate looked for on every ply
+const MISSED_MATE_MOVES: u32 = 3;
+/// Material (centipawns) a capture must win for missing it to matter
+const WINNING_CAPTURE: i32 = 300;
+
+/// Analyze a PGN game and produce move-by-move analysis. When an opening
+/// book is given, its moves count as Book as well as the ECO table's.
 pub fn analyze_game(
     pgn_text: &str,
     white: &str,
@@ -11,9 +41,10 @@ pub fn analyze_game(
     time_class: &str,
     game_url: &str,
     end_time: u64,
+    style: &CoachStyle,
+    book: Option<&polyglot::Book>,
 ) -> Result<GameAnalysis, String> {
     let moves_text = extract_moves_from_pgn(pgn_text);
-    let opening_name = extract_opening_from_pgn(pgn_text);
     let date_str = extract_date_from_pgn(pgn_text)
         .unwrap_or_else(|| format_timestamp(end_time));
 
@@ -23,10 +54,19 @@ pub fn analyze_game(
 
     let san_moves = parse_san_moves(&moves_text);
 
-    // Book moves database (simplified ECO)
-    let book_moves_count = detect_book_moves(&san_moves);
+    // Book moves and opening name come from the positions reached, so
+    // transpositions into a known line are recognised
+    let opening = eco::classify(&san_moves);
+    let book_moves_count = opening.book_plies;
+    let opening_name = opening
+        .opening
+        .map(|o| o.name.clone())
+        .or_else(|| extract_opening_from_pgn(pgn_text));
 
-    let mut current_eval: f64 = 0.3; // Slight white opening advantage
+    // Square and piece taken by the previous move when it was a capture, to spot recaptures
+    let mut last_capture: Option<(Square, Role)> = None;
+    // Still following the Polyglot book
+    let mut in_book = book.is_some();
 
     for (i, san_str) in san_moves.iter().enumerate() {
         let color = if i % 2 == 0 { "white" } else { "black" };
@@ -42,29 +82,42 @@ pub fn analyze_game(
             Err(_) => continue,
         };
 
-        let is_book = i < book_moves_count;
+        let was_book = move_analyses.last().is_none_or(|m: &MoveAnalysis| m.is_book_move);
+        in_book = in_book && book.is_some_and(|b| b.contains(&pos, &m));
+        let is_book = in_book || i < book_moves_count;
+        // Where the game leaves theory, show what the book would have played
+        let book_alternatives = match book {
+            Some(b) if was_book && !is_book => b.moves(&pos),
+            _ => Vec::new(),
+        };
+        let missed_win = if is_book { None } else { detect_missed_win(&pos, &m) };
+        let ply_eval = evaluate_ply(&pos, &m);
 
-        // Classify the move based on position analysis
-        let classification = if is_book {
+        // Classify the move by how much of the engine evaluation it keeps
+        let mut classification = if is_book {
             MoveClassification::Book
         } else {
-            classify_move(&pos, &m, i, san_moves.len())
+            classify_move(&pos, &m, &ply_eval, last_capture)
         };
-
-        // Update eval score based on classification
-        let sign = if color == "white" { 1.0 } else { -1.0 };
-        match classification {
-            MoveClassification::Blunder => current_eval -= sign * 2.5,
-            MoveClassification::Mistake => current_eval -= sign * 1.5,
-            MoveClassification::Inaccuracy => current_eval -= sign * 0.6,
-            MoveClassification::Brilliant => current_eval += sign * 0.4,
-            MoveClassification::Great => current_eval += sign * 0.2,
-            MoveClassification::Best => current_eval += sign * 0.05,
-            _ => {}
+        // Letting a forced win slip is at least a Mistake
+        if missed_win.is_some()
+            && !matches!(classification, MoveClassification::Mistake | MoveClassification::Blunder)
+        {
+            classification = MoveClassification::Mistake;
         }
-        current_eval = current_eval.clamp(-10.0, 10.0);
 
-        let comment = generate_comment(&classification, san_str, color, current_move_number);
+        // Evaluation after the move in pawns, positive = white advantage
+        let sign = if color == "white" { 1.0 } else { -1.0 };
+        let eval_score = (sign * ply_eval.played as f64 / 100.0).clamp(-10.0, 10.0);
+
+        let comment = generate_comment(&MoveContext {
+            pos: &pos,
+            played: &m,
+            best: ply_eval.best().map(|line| line.best()),
+            last_capture,
+            classification: &classification,
+            style,
+        });
 
         // Extract played move squares
         let (played_from, played_to) = extract_move_squares(&m);
@@ -72,16 +125,25 @@ pub fn analyze_game(
         // Store FEN before the move
         let fen_before = format_fen(&pos);
 
-        // Find best alternative move for bad moves
-        let (best_move_san, best_from, best_to) = if matches!(
-            classification,
-            MoveClassification::Inaccuracy | MoveClassification::Mistake | MoveClassification::Blunder
-        ) {
-            find_best_move(&pos, &m)
-        } else {
-            (None, None, None)
+        // The engine's choice, for bad moves
+        let (best_move_san, best_from, best_to) = match ply_eval.best() {
+            Some(best)
+                if matches!(
+                    classification,
+                    MoveClassification::Inaccuracy | MoveClassification::Mistake | MoveClassification::Blunder
+                ) =>
+            {
+                let (from, to) = extract_move_squares(best.best());
+                (Some(San::from_move(&pos, best.best()).to_string()), Some(from), Some(to))
+            }
+            _ => (None, None, None),
         };
 
+        let candidates = if is_book { Vec::new() } else { candidates(&pos, &ply_eval.lines) };
+        let motifs = tactics::detect_motifs(&pos, &m);
+        let threats_before = threats::detect_threats(&pos);
+        last_capture = m.capture().map(|role| (m.to(), role));
+
         pos.play_unchecked(&m);
         let fen_after = format_fen(&pos);
 
@@ -99,7 +161,12 @@ pub fn analyze_game(
             best_from,
             best_to,
             fen_before,
-            eval_score: (current_eval * 100.0).round() / 100.0,
+            eval_score: (eval_score * 100.0).round() / 100.0,
+            motifs,
+            missed_win,
+            threats_before,
+            candidates,
+            book_alternatives,
         });
 
         if i % 2 == 1 {
@@ -107,8 +174,11 @@ pub fn analyze_game(
         }
     }
 
-    let summary = calculate_summary(&move_analyses, &opening_name);
-    let key_moments = detect_key_moments(&move_analyses);
+    let missed_punishments = detect_missed_punishments(&move_analyses);
+    let mut summary = calculate_summary(&move_analyses, &opening_name);
+    summary.missed_punishments = missed_punishments.len() as u32;
+    summary.eco = opening.opening.map(|o| o.eco.clone());
+    let key_moments = detect_key_moments(&move_analyses, style.language);
 
     Ok(GameAnalysis {
         game_url: game_url.to_string(),
@@ -122,6 +192,11 @@ pub fn analyze_game(
         moves: move_analyses,
         summary,
         key_moments,
+        missed_punishments,
+        // Needs the user's repertoire, filled in by the caller
+        repertoire_deviation: None,
+        comment_style: *style,
+        format_version: FORMAT_VERSION,
     })
 }
 
@@ -241,158 +316,266 @@ fn parse_san_moves(moves_text: &str) -> Vec<String> {
     moves
 }
 
-fn detect_book_moves(moves: &[String]) -> usize {
-    // Common book patterns - first N moves that match known openings
-    // This is a simplified detection; real engines use ECO databases
-    let common_openings: Vec<Vec<&str>> = vec![
-        // Italian Game
-        vec!["e4", "e5", "Nf3", "Nc6", "Bc4", "Bc5"],
-        // Ruy Lopez
-        vec!["e4", "e5", "Nf3", "Nc6", "Bb5"],
-        // Sicilian Defense
-        vec!["e4", "c5", "Nf3", "d6", "d4", "cxd4", "Nxd4"],
-        // French Defense
-        vec!["e4", "e6", "d4", "d5"],
-        // Caro-Kann
-        vec!["e4", "c6", "d4", "d5"],
-        // Queen's Gambit
-        vec!["d4", "d5", "c4"],
-        // King's Indian
-        vec!["d4", "Nf6", "c4", "g6"],
-        // English Opening
-        vec!["c4", "e5"],
-        // Scotch Game
-        vec!["e4", "e5", "Nf3", "Nc6", "d4"],
-        // Pirc Defense
-        vec!["e4", "d6", "d4", "Nf6"],
-        // London System
-        vec!["d4", "d5", "Bf4"],
-        // Scandinavian
-        vec!["e4", "d5"],
-    ];
-
-    let move_strs: Vec<&str> = moves.iter().map(|m| {
-        m.trim_end_matches('+')
-            .trim_end_matches('#')
-    }).collect();
-
-    let mut best_match = 0;
-    for opening in &common_openings {
-        if opening.len() <= move_strs.len() {
-            let matches = opening
-                .iter()
-                .zip(move_strs.iter())
-                .take_while(|(a, b)| a == b)
-                .count();
-            if matches == opening.len() {
-                best_match = best_match.max(matches);
-            }
-        }
+/// Engine scores around one ply, in centipawns for the side to move
+struct PlyEval {
+    // Top engine lines, best first
+    lines: Vec<engine::Line>,
+    played: i32,
+}
+
+impl PlyEval {
+    fn best(&self) -> Option<&engine::Line> {
+        self.lines.first()
     }
 
-    // Add a couple more moves as "theory" after the known opening
-    if best_match > 0 {
-        best_match + 2
-    } else {
-        // At least first 2 moves are usually "book"
-        2.min(moves.len())
+    fn second(&self) -> Option<&engine::Line> {
+        self.lines.get(1)
     }
 }
 
+fn evaluate_ply(pos: &Chess, m: &shakmaty::Move) -> PlyEval {
+    let lines = engine::analyse(pos, Limits::QUICK, CANDIDATES);
+
+    let played = match lines.iter().find(|l| l.best() == m) {
+        Some(line) => line.score,
+        None => {
+            // Search the reply one ply shallower so both scores see as far
+            let mut after = pos.clone();
+            after.play_unchecked(m);
+            let limits = Limits {
+                depth: Limits::QUICK.depth - 1,
+                ..Limits::QUICK
+            };
+            match engine::analyse(&after, limits, 1).first() {
+                Some(reply) => -reply.score,
+                None if after.is_checkmate() => engine::MATE - 1,
+                None => 0,
+            }
+        }
+    };
+
+    PlyEval { lines, played }
+}
+
+/// Engine lines as candidate moves, scored from White's side like `eval_score`
+fn candidates(pos: &Chess, lines: &[engine::Line]) -> Vec<Candidate> {
+    let sign = if pos.turn() == shakmaty::Color::White { 1 } else { -1 };
+    lines
+        .iter()
+        .map(|line| {
+            let (from, to) = extract_move_squares(line.best());
+            let pv: Vec<shakmaty::Move> = line.moves.iter().take(CANDIDATE_PV_PLIES).cloned().collect();
+            let eval = ((sign * line.score) as f64 / 100.0).clamp(-10.0, 10.0);
+            Candidate {
+                san: San::from_move(pos, line.best()).to_string(),
+                from,
+                to,
+                eval: (eval * 100.0).round() / 100.0,
+                mate_in: engine::mate_in(line.score).map(|n| sign * n),
+                pv: san_line(pos, &pv),
+            }
+        })
+        .collect()
+}
+
+/// Expected score in [-1, 1] for a centipawn evaluation (Lichess' curve)
+fn winning_chances(cp: i32) -> f64 {
+    let cp = cp.clamp(-1000, 1000) as f64;
+    2.0 / (1.0 + (-0.00368208 * cp).exp()) - 1.0
+}
+
 fn classify_move(
     pos: &Chess,
     m: &shakmaty::Move,
-    move_index: usize,
-    total_moves: usize,
+    eval: &PlyEval,
+    last_capture: Option<(Square, Role)>,
 ) -> MoveClassification {
     let legal_moves = pos.legal_moves();
-    
+
     // Only one legal move = forced
     if legal_moves.len() == 1 {
         return MoveClassification::ForcedMove;
     }
 
-    // Heuristic-based classification
-    // In a real app, you'd use a chess engine (Stockfish) for evaluation
-    let is_capture = m.is_capture();
-    let is_promotion = m.is_promotion();
-    let is_castle = m.is_castle();
+    let Some(best) = eval.best() else {
+        return MoveClassification::Good;
+    };
 
-    // Check if the move results in check
-    let mut test_pos = pos.clone();
-    test_pos.play_unchecked(m);
-    let gives_check = test_pos.is_check();
-    let is_checkmate = test_pos.is_checkmate();
+    let loss = winning_chances(best.score) - winning_chances(eval.played);
+    if loss >= BLUNDER {
+        return MoveClassification::Blunder;
+    } else if loss >= MISTAKE {
+        return MoveClassification::Mistake;
+    } else if loss >= INACCURACY {
+        return MoveClassification::Inaccuracy;
+    }
+
+    if best.score - eval.played > BEST_MARGIN {
+        return MoveClassification::Good;
+    }
 
-    if is_checkmate {
+    if is_brilliant(pos, m, eval.played) {
         return MoveClassification::Brilliant;
     }
 
-    // Use randomized heuristic for demo purposes
-    // In production, integrate with Stockfish UCI protocol
-    let hash = simple_hash(move_index, total_moves, is_capture, gives_check);
+    // Great: the only move that keeps the evaluation, unless it is a plain recapture
+    let only_move = eval
+        .second()
+        .is_some_and(|second| winning_chances(eval.played) - winning_chances(second.score) >= MISTAKE);
+    if only_move && last_capture.map(|(sq, _)| sq) != Some(m.to()) {
+        return MoveClassification::Great;
+    }
 
-    if is_promotion && gives_check {
-        MoveClassification::Brilliant
-    } else if is_castle && move_index < 20 {
-        MoveClassification::Good
-    } else if gives_check && is_capture {
-        MoveClassification::Great
-    } else {
-        match hash % 100 {
-            0..=3 => MoveClassification::Brilliant,
-            4..=10 => MoveClassification::Great,
-            11..=35 => MoveClassification::Best,
-            36..=60 => MoveClassification::Good,
-            61..=75 => MoveClassification::Good,
-            76..=85 => MoveClassification::Inaccuracy,
-            86..=93 => MoveClassification::Mistake,
-            _ => MoveClassification::Blunder,
+    MoveClassification::Best
+}
+
+/// A sound sacrifice that a shallow look would not find: the move gives up
+/// material by static exchange, the position stays at least equal, and a
+/// one-ply search prefers something else
+fn is_brilliant(pos: &Chess, m: &shakmaty::Move, played: i32) -> bool {
+    let mover = pos.turn();
+    let mut after = pos.clone();
+    after.play_unchecked(m);
+
+    let captured = m.capture().map(tactics::piece_value).unwrap_or(0);
+    let newly_hanging = see::worst_hanging_loss(after.board(), mover)
+        - see::worst_hanging_loss(pos.board(), mover)
+        - captured;
+    let sacrifice = see::see(pos, m) <= -SACRIFICE || newly_hanging >= SACRIFICE;
+    if !sacrifice || played < -50 {
+        return false;
+    }
+
+    let shallow = engine::analyse(pos, Limits { depth: 1, nodes: 20_000 }, 1);
+    shallow.first().is_some_and(|l| l.best() != m)
+}
+
+/// A forced mate within `MISSED_MATE_MOVES`, or a capture winning at least
+/// `WINNING_CAPTURE`, that the played move gives up
+fn detect_missed_win(pos: &Chess, m: &shakmaty::Move) -> Option<MissedWin> {
+    let mut after = pos.clone();
+    after.play_unchecked(m);
+    if after.is_checkmate() {
+        return None;
+    }
+
+    if let Some(line) = engine::find_mate(pos, MISSED_MATE_MOVES) {
+        let mate_in = line.len().div_ceil(2) as u32;
+        // Fine as long as the played move still mates in time
+        if engine::is_mated_within(&after, mate_in - 1) {
+            return None;
         }
+        return Some(MissedWin {
+            kind: MissedWinKind::Mate,
+            line: san_line(pos, &line),
+            mate_in: Some(mate_in),
+            gain: 0,
+        });
+    }
+
+    let played_gain = see::see(pos, m).max(0);
+    let (capture, gain) = pos
+        .capture_moves()
+        .into_iter()
+        .filter(|c| c != m)
+        .map(|c| {
+            let gain = see::see(pos, &c);
+            (c, gain)
+        })
+        .max_by_key(|(_, gain)| *gain)?;
+    if gain < WINNING_CAPTURE || gain - played_gain < WINNING_CAPTURE {
+        return None;
+    }
+
+    // Check with a short search that the capture really comes out ahead of
+    // the move played (no mate or bigger threat behind it)
+    let limits = Limits { depth: 3, nodes: 30_000 };
+    let mut taken = pos.clone();
+    taken.play_unchecked(&capture);
+    let reply = engine::analyse(&taken, limits, 1).into_iter().next();
+    let capture_score = reply.as_ref().map(|l| -l.score).unwrap_or(0);
+    let played_score = engine::analyse(&after, limits, 1)
+        .first()
+        .map(|l| -l.score)
+        .unwrap_or(0);
+    if capture_score - played_score < WINNING_CAPTURE {
+        return None;
     }
+
+    let mut line = vec![capture];
+    line.extend(reply.map(|l| l.moves).unwrap_or_default().into_iter().take(2));
+    Some(MissedWin {
+        kind: MissedWinKind::Material,
+        line: san_line(pos, &line),
+        mate_in: None,
+        gain,
+    })
+}
+
+/// SAN of a line of moves played from `pos`
+pub fn san_line(pos: &Chess, moves: &[shakmaty::Move]) -> Vec<String> {
+    let mut replay = pos.clone();
+    moves
+        .iter()
+        .map(|m| {
+            let san = SanPlus::from_move(replay.clone(), m).to_string();
+            replay.play_unchecked(m);
+            san
+        })
+        .collect()
 }
 
-fn simple_hash(move_index: usize, total: usize, capture: bool, check: bool) -> usize {
-    let mut h = move_index.wrapping_mul(2654435761);
-    h ^= total.wrapping_mul(2246822519);
-    if capture { h = h.wrapping_add(13); }
-    if check { h = h.wrapping_add(37); }
-    h % 100
+/// What is known about a move when writing its offline comment
+struct MoveContext<'a> {
+    pos: &'a Chess,
+    played: &'a shakmaty::Move,
+    /// The engine's choice, when it searched the position
+    best: Option<&'a shakmaty::Move>,
+    /// Square and piece taken by the previous move, to spot recaptures
+    last_capture: Option<(Square, Role)>,
+    classification: &'a MoveClassification,
+    style: &'a CoachStyle,
 }
 
-fn generate_comment(
-    classification: &MoveClassification,
-    san: &str,
-    color: &str,
-    move_number: u32,
-) -> Option<String> {
-    let color_name = if color == "white" { "White" } else { "Black" };
-
-    match classification {
-        MoveClassification::Brilliant => Some(format!(
-            "Brilliant move! {}. {} is an exceptional find.",
-            move_number, san
-        )),
-        MoveClassification::Great => Some(format!(
-            "Great move by {}. {} maintains strong pressure.",
-            color_name, san
-        )),
-        MoveClassification::Best => None,
-        MoveClassification::Good => None,
-        MoveClassification::Book => Some("Theory move.".to_string()),
-        MoveClassification::Inaccuracy => Some(format!(
-            "Inaccuracy. {} could have played a more precise move here.",
-            color_name
-        )),
-        MoveClassification::Mistake => Some(format!(
-            "Mistake! {}. {} gives away part of {}'s advantage.",
-            move_number, san, color_name
-        )),
-        MoveClassification::Blunder => Some(format!(
-            "Blunder! {}. {} is a serious error that changes the evaluation significantly.",
-            move_number, san
-        )),
-        MoveClassification::ForcedMove => Some("Only legal move.".to_string()),
+/// Comment a move from what is detected on the board. Without a detected
+/// fact, a bad move names the engine's choice and a good one gets nothing.
+fn generate_comment(ctx: &MoveContext) -> Option<String> {
+    let lang = ctx.style.language;
+    let label = match ctx.classification {
+        MoveClassification::Brilliant => Some(Text::Brilliant),
+        MoveClassification::Great => Some(Text::Great),
+        MoveClassification::Best | MoveClassification::Good => None,
+        MoveClassification::Inaccuracy => Some(Text::Inaccuracy),
+        MoveClassification::Mistake => Some(Text::Mistake),
+        MoveClassification::Blunder => Some(Text::Blunder),
+        MoveClassification::Book => Some(Text::Book),
+        MoveClassification::ForcedMove => Some(Text::Forced),
+    };
+    let is_error = matches!(
+        ctx.classification,
+        MoveClassification::Inaccuracy | MoveClassification::Mistake | MoveClassification::Blunder
+    );
+
+    // Prefer an explanation built from what actually happens on the board
+    let explanation = explain::explain_move(ctx.pos, ctx.played, ctx.last_capture, ctx.classification, lang);
+    let comment = match (explanation, label) {
+        (Some(explanation), Some(l)) => format!("{} {}", i18n::text(lang, l), explanation),
+        (Some(explanation), None) => explanation,
+        (None, Some(l)) if is_error => {
+            let best = ctx.best.filter(|&b| b != ctx.played)?;
+            let best = San::from_move(ctx.pos, best).to_string();
+            let better = i18n::render(i18n::text(lang, Text::BetterMove), &[("best", best)]);
+            format!("{} {}", i18n::text(lang, l), better)
+        }
+        // Theory and forced moves are worth their label alone
+        (None, Some(l @ (Text::Book | Text::Forced))) => i18n::text(lang, l).to_string(),
+        (None, _) => return None,
+    };
+
+    if ctx.style.level == CoachLevel::Beginner && is_error {
+        Some(format!("{} {}", comment, i18n::text(lang, Text::BeginnerTip)))
+    } else {
+        Some(comment)
     }
 }
 
@@ -402,7 +585,7 @@ fn square_to_name(sq: Square) -> String {
     format!("{}{}", file, rank)
 }
 
-fn extract_move_squares(m: &shakmaty::Move) -> (String, String) {
+pub fn extract_move_squares(m: &shakmaty::Move) -> (String, String) {
     match m {
         shakmaty::Move::Normal { from, to, .. } => {
             (square_to_name(*from), square_to_name(*to))
@@ -427,113 +610,16 @@ fn extract_move_squares(m: &shakmaty::Move) -> (String, String) {
     }
 }
 
-fn find_best_move(pos: &Chess, played_move: &shakmaty::Move) -> (Option<String>, Option<String>, Option<String>) {
-    let legal_moves = pos.legal_moves();
-    
-    if legal_moves.len() <= 1 {
-        return (None, None, None);
-    }
-
-    // Evaluate each legal move and find the best alternative
-    let mut best_score = i32::MIN;
-    let mut best_m: Option<&shakmaty::Move> = None;
-    
-    for m in &legal_moves {
-        // Skip the move that was actually played
-        if m == played_move {
-            continue;
-        }
-        
-        let score = evaluate_move(pos, m);
-        if score > best_score {
-            best_score = score;
-            best_m = Some(m);
-        }
-    }
-
-    if let Some(m) = best_m {
-        let (from, to) = extract_move_squares(m);
-        let san = San::from_move(pos, m);
-        (Some(san.to_string()), Some(from), Some(to))
-    } else {
-        (None, None, None)
-    }
+/// Parse a FEN (or the EPD stored in `fen_before` / `fen_after`)
+pub fn parse_fen(fen: &str) -> Result<Chess, String> {
+    fen.trim()
+        .parse::<shakmaty::fen::Fen>()
+        .map_err(|e| format!("Invalid FEN: {}", e))?
+        .into_position(shakmaty::CastlingMode::Standard)
+        .map_err(|e| format!("Illegal position: {}", e))
 }
 
-fn evaluate_move(pos: &Chess, m: &shakmaty::Move) -> i32 {
-    let mut score = 0i32;
-    
-    // Play the move on a test position
-    let mut test_pos = pos.clone();
-    test_pos.play_unchecked(m);
-    
-    // Checkmate is the best possible move
-    if test_pos.is_checkmate() {
-        return 10000;
-    }
-    
-    // Check gives bonus
-    if test_pos.is_check() {
-        score += 50;
-    }
-    
-    // Captures are generally good - value of captured piece
-    if m.is_capture() {
-        score += match m {
-            shakmaty::Move::Normal { to, .. } | shakmaty::Move::EnPassant { to, .. } => {
-                // Estimate captured piece value from the board
-                if let Some(piece) = pos.board().piece_at(*to) {
-                    match piece.role {
-                        shakmaty::Role::Pawn => 100,
-                        shakmaty::Role::Knight => 320,
-                        shakmaty::Role::Bishop => 330,
-                        shakmaty::Role::Rook => 500,
-                        shakmaty::Role::Queen => 900,
-                        shakmaty::Role::King => 0,
-                    }
-                } else if matches!(m, shakmaty::Move::EnPassant { .. }) {
-                    100 // En passant captures a pawn
-                } else {
-                    0
-                }
-            }
-            _ => 0,
-        };
-    }
-    
-    // Promotion is great
-    if m.is_promotion() {
-        score += 800;
-    }
-    
-    // Castling is generally good in the opening
-    if m.is_castle() {
-        score += 60;
-    }
-    
-    // Center control bonus - moving pieces to center
-    match m {
-        shakmaty::Move::Normal { to, .. } => {
-            let file = to.file() as i32;
-            let rank = to.rank() as i32;
-            // Bonus for central squares
-            let center_bonus = 4 - ((file - 3).abs() + (rank - 3).abs());
-            score += center_bonus.max(0) * 5;
-        }
-        _ => {}
-    }
-    
-    // Penalty if the move leaves pieces hanging (opponent can recapture)
-    let opponent_moves = test_pos.legal_moves();
-    let responding_captures = opponent_moves.iter().filter(|om| om.is_capture()).count();
-    if responding_captures > 0 && !m.is_capture() {
-        score -= 20;
-    }
-    
-    score
-}
-
-fn format_fen(pos: &Chess) -> String {
+pub fn format_fen(pos: &Chess) -> String {
     let epd = Epd::from_position(pos.clone(), shakmaty::EnPassantMode::Legal);
     epd.to_string()
 }
@@ -560,13 +646,7 @@ fn calculate_summary(moves: &[MoveAnalysis], opening: &Option<String>) -> GameSu
         }
     }
 
-    let total = moves.len() as f64;
-    let good_total = (brilliancies + great_moves + best_moves + good_moves) as f64;
-    let accuracy = if total > 0.0 {
-        (good_total / total * 100.0).min(100.0)
-    } else {
-        0.0
-    };
+    let accuracy = accuracy(brilliancies + great_moves + best_moves + good_moves, moves.len());
 
     GameSummary {
         total_moves: moves.len() as u32,
@@ -577,55 +657,141 @@ fn calculate_summary(moves: &[MoveAnalysis], opening: &Option<String>) -> GameSu
         inaccuracies,
         mistakes,
         blunders,
-        accuracy: (accuracy * 10.0).round() / 10.0,
+        accuracy,
         opening_name: opening.clone(),
+        missed_punishments: 0,
+        eco: None,
     }
 }
 
-fn detect_key_moments(moves: &[MoveAnalysis]) -> Vec<KeyMoment> {
-    let mut moments = Vec::new();
+/// Share of good or better moves, in percent to one decimal
+fn accuracy(good: u32, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    let accuracy = (good as f64 / total as f64 * 100.0).min(100.0);
+    (accuracy * 10.0).round() / 10.0
+}
+
+/// Accuracy of one side's moves, on the same scale as the game summary
+pub fn side_accuracy(moves: &[MoveAnalysis], color: &str) -> f64 {
+    let side: Vec<&MoveAnalysis> = moves.iter().filter(|m| m.color == color).collect();
+    let good = side
+        .iter()
+        .filter(|m| {
+            matches!(
+                m.classification,
+                MoveClassification::Brilliant
+                    | MoveClassification::Great
+                    | MoveClassification::Best
+                    | MoveClassification::Good
+            )
+        })
+        .count();
+    accuracy(good as u32, side.len())
+}
 
+/// SAN moves of a PGN game, without numbers, comments or result
+pub fn pgn_san_moves(pgn: &str) -> Vec<String> {
+    parse_san_moves(&extract_moves_from_pgn(pgn))
+}
+
+/// Replies to an opponent's Mistake or Blunder that gave back more than half
+/// of the advantage the error handed over
+fn detect_missed_punishments(moves: &[MoveAnalysis]) -> Vec<MissedPunishment> {
+    let mut events = Vec::new();
+
+    for (i, pair) in moves.windows(2).enumerate() {
+        let (error, reply) = (&pair[0], &pair[1]);
+        if !matches!(error.classification, MoveClassification::Mistake | MoveClassification::Blunder) {
+            continue;
+        }
+        let (Some(before), Some(best)) = (error.candidates.first(), reply.candidates.first()) else {
+            continue;
+        };
+
+        // Winning chances for the replying side
+        let sign = if reply.color == "white" { 1.0 } else { -1.0 };
+        let chances = |eval: f64| sign * winning_chances((eval * 100.0) as i32);
+        let gained = chances(best.eval) - chances(before.eval);
+        if gained < INACCURACY {
+            continue;
+        }
+        let kept = chances(reply.eval_score) - chances(before.eval);
+        if kept >= gained / 2.0 {
+            continue;
+        }
+
+        events.push(MissedPunishment {
+            move_index: i + 1,
+            move_number: reply.move_number,
+            color: reply.color.clone(),
+            error: error.san.clone(),
+            played: reply.san.clone(),
+            punishment: best.san.clone(),
+            eval_before_error: before.eval,
+            eval_available: best.eval,
+            eval_after: reply.eval_score,
+        });
+    }
+
+    events
+}
+
+/// Win-probability drop (in winning chances, [-1, 1]) that makes a turning point
+const SWING: f64 = 0.2;
+const CRITICAL_SWING: f64 = 0.4;
+/// Winning chances from which the game counts as decided
+const DECIDED: f64 = 0.7;
+/// Most key moments shown on the timeline
+const MAX_KEY_MOMENTS: usize = 7;
+
+/// Turning points from the evaluation curve: big win-probability swings,
+/// only-move positions, missed wins, and the move that decided the game
+fn detect_key_moments(moves: &[MoveAnalysis], language: Language) -> Vec<KeyMoment> {
+    // Winning chances for White after each move
+    let curve: Vec<f64> = moves
+        .iter()
+        .map(|m| winning_chances((m.eval_score * 100.0) as i32))
+        .collect();
+    let decisive = decisive_move(&curve);
+
+    let mut moments = Vec::new();
     for (i, m) in moves.iter().enumerate() {
-        let (severity, description) = match m.classification {
-            MoveClassification::Blunder => {
-                let desc = format!(
-                    "{}. {} — a blunder that significantly changed the game.",
-                    m.move_number, m.san
-                );
-                ("critical".to_string(), desc)
-            }
-            MoveClassification::Mistake => {
-                let desc = format!(
-                    "{}. {} — a mistake that gave away advantage.",
-                    m.move_number, m.san
-                );
-                ("major".to_string(), desc)
-            }
-            MoveClassification::Brilliant => {
-                let desc = format!(
-                    "{}. {} — a brilliant find!",
-                    m.move_number, m.san
-                );
-                ("notable".to_string(), desc)
+        let sign = if m.color == "white" { 1.0 } else { -1.0 };
+        // The position's value before the move is what its best line scores.
+        // Book and forced moves, and moves the engine did not score, are no swings.
+        let eval_before = m.candidates.first().map(|best| best.eval);
+        let chosen = !matches!(m.classification, MoveClassification::Book | MoveClassification::ForcedMove);
+        let drop = match eval_before {
+            Some(before) if chosen => sign * (winning_chances((before * 100.0) as i32) - curve[i]),
+            _ => 0.0,
+        };
+        let only_move = match (m.candidates.first(), m.candidates.get(1)) {
+            (Some(best), Some(second)) => {
+                let chances = |c: &Candidate| sign * winning_chances((c.eval * 100.0) as i32);
+                chances(best) - chances(second) >= SWING
             }
-            MoveClassification::Inaccuracy => {
-                // Only mark inaccuracies as key moments if followed by another bad move
-                if i + 1 < moves.len() {
-                    match moves[i + 1].classification {
-                        MoveClassification::Mistake | MoveClassification::Blunder | MoveClassification::Inaccuracy => {
-                            let desc = format!(
-                                "{}. {} — inaccuracy leading to further trouble.",
-                                m.move_number, m.san
-                            );
-                            ("notable".to_string(), desc)
-                        }
-                        _ => continue,
-                    }
-                } else {
-                    continue;
-                }
+            _ => false,
+        };
+
+        let (reason, severity) = if let Some(missed) = &m.missed_win {
+            match missed.kind {
+                MissedWinKind::Mate => (MomentReason::MissedMate, Severity::Critical),
+                MissedWinKind::Material => (MomentReason::MissedWin, Severity::Critical),
             }
-            _ => continue,
+        } else if decisive == Some(i) {
+            (MomentReason::Decisive, Severity::Critical)
+        } else if drop >= SWING {
+            let severity = if drop >= CRITICAL_SWING { Severity::Critical } else { Severity::Major };
+            let reason = if only_move { MomentReason::OnlyMoveMissed } else { MomentReason::Swing };
+            (reason, severity)
+        } else if m.classification == MoveClassification::Brilliant {
+            (MomentReason::Brilliant, Severity::Notable)
+        } else if only_move && m.classification == MoveClassification::Great && !m.san.ends_with('#') {
+            (MomentReason::OnlyMoveFound, Severity::Notable)
+        } else {
+            continue;
         };
 
         moments.push(KeyMoment {
@@ -634,25 +800,112 @@ fn detect_key_moments(moves: &[MoveAnalysis]) -> Vec<KeyMoment> {
             san: m.san.clone(),
             color: m.color.clone(),
             classification: format!("{:?}", m.classification),
-            description,
+            description: describe_moment(m, reason, eval_before.unwrap_or(m.eval_score), language),
             severity,
+            reason,
         });
     }
 
-    // Sort by severity (critical first) and keep top 5
-    moments.sort_by(|a, b| {
-        let severity_order = |s: &str| match s {
-            "critical" => 0,
-            "major" => 1,
-            "notable" => 2,
-            _ => 3,
-        };
-        severity_order(&a.severity)
-            .cmp(&severity_order(&b.severity))
-            .then(a.move_index.cmp(&b.move_index))
-    });
-    moments.truncate(7);
-    // Re-sort by move index for chronological display
+    // Keep the most severe, then show them in game order
+    moments.sort_by_key(|m| (m.severity, m.move_index));
+    moments.truncate(MAX_KEY_MOMENTS);
     moments.sort_by_key(|m| m.move_index);
     moments
 }
+
+/// Index of the move after which one side stayed clearly winning to the end
+fn decisive_move(curve: &[f64]) -> Option<usize> {
+    let last = *curve.last()?;
+    if last.abs() < DECIDED {
+        return None;
+    }
+    let sign = last.signum();
+    let mut first = curve.len() - 1;
+    while first > 0 && sign * curve[first - 1] >= DECIDED {
+        first -= 1;
+    }
+    // Won from the first move on: nothing decided it
+    if first == 0 {
+        return None;
+    }
+    Some(first)
+}
+
+fn describe_moment(m: &MoveAnalysis, reason: MomentReason, eval_before: f64, language: Language) -> String {
+    let missed = m.missed_win.as_ref();
+    let (key, mut args) = match reason {
+        MomentReason::MissedMate => (
+            Text::MomentMissedMate,
+            vec![
+                ("mate_in", missed.and_then(|w| w.mate_in).unwrap_or(1).to_string()),
+                ("line", missed.map(|w| w.line.join(" ")).unwrap_or_default()),
+            ],
+        ),
+        MomentReason::MissedWin => (
+            Text::MomentMissedWin,
+            vec![("capture", missed.and_then(|w| w.line.first().cloned()).unwrap_or_default())],
+        ),
+        MomentReason::Decisive => (Text::MomentDecisive, vec![]),
+        MomentReason::Swing => (
+            Text::MomentSwing,
+            vec![
+                ("before", format!("{:+.1}", eval_before)),
+                ("after", format!("{:+.1}", m.eval_score)),
+            ],
+        ),
+        MomentReason::OnlyMoveMissed => (
+            Text::MomentOnlyMoveMissed,
+            vec![("best", m.candidates.first().map(|c| c.san.clone()).unwrap_or_default())],
+        ),
+        MomentReason::OnlyMoveFound => (Text::MomentOnlyMoveFound, vec![]),
+        MomentReason::Brilliant => (Text::MomentBrilliant, vec![]),
+    };
+    args.push(("number", m.move_number.to_string()));
+    args.push(("move", m.san.clone()));
+    i18n::render(i18n::text(language, key), &args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::{fen::Fen, uci::UciMove, CastlingMode};
+
+    fn brilliant(fen: &str, uci: &str) -> bool {
+        let pos: Chess = fen
+            .parse::<Fen>()
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let m = uci.parse::<UciMove>().unwrap().to_move(&pos).unwrap();
+        is_brilliant(&pos, &m, evaluate_ply(&pos, &m).played)
+    }
+
+    #[test]
+    fn smothered_mate_queen_sacrifice_is_brilliant() {
+        // 1.Qg8+ Rxg8 2.Nf7#
+        assert!(brilliant("3r3k/6pp/7N/8/8/1Q6/6PP/6K1 w - - 0 1", "b3g8"));
+    }
+
+    #[test]
+    fn anastasia_mate_queen_sacrifice_is_brilliant() {
+        // 1.Qxh7+ Kxh7 2.Rh3#
+        assert!(brilliant("5r1k/4Nppp/8/7Q/8/3R4/6PP/6K1 w - - 0 1", "h5h7"));
+    }
+
+    #[test]
+    fn back_rank_deflection_is_not_brilliant() {
+        // 1.Qd8+ Rxd8 2.Rxd8#, found by a one-ply search
+        assert!(!brilliant("2r3k1/5ppp/8/8/3Q4/8/5PPP/3R2K1 w - - 0 1", "d4d8"));
+    }
+
+    #[test]
+    fn exchange_sacrifice_winning_the_queen_is_not_brilliant() {
+        // 1.Rxe5 dxe5 2.Qxd8+, a plain capture sequence
+        assert!(!brilliant("3q2k1/5ppp/3p4/4p3/8/8/5PPP/3QR1K1 w - - 0 1", "e1e5"));
+    }
+
+    #[test]
+    fn unsound_sacrifice_is_not_brilliant() {
+        assert!(!brilliant("2r3k1/5ppp/8/8/3Q4/8/5PPP/6K1 w - - 0 1", "d4d8"));
+    }
+}
@@ -0,0 +1,242 @@
+use crate::analysis;
+use crate::models::{Lesson, Opening};
+use crate::polyglot;
+use serde::Deserialize;
+use shakmaty::{san::SanPlus, Chess, Position};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Opening file format read by this build. Version 2 adds ids, parents and
+/// plans; version 1 files are still read, with ids made from the names.
+const OPENINGS_VERSION: u32 = 2;
+/// Lesson file format read by this build; files of another version are skipped
+const LESSONS_VERSION: u32 = 1;
+
+const OPENINGS_JSON: &str = include_str!("../resources/openings.json");
+const LESSONS_JSON: &str = include_str!("../resources/lessons.json");
+
+/// Folder in the app data dir for the user's own `openings.json` and
+/// `lessons.json`. Their entries replace bundled ones of the same name or id.
+const USER_DIR: &str = "catalog";
+
+#[derive(Deserialize)]
+struct OpeningFile {
+    version: u32,
+    openings: Vec<Opening>,
+}
+
+#[derive(Deserialize)]
+struct LessonFile {
+    version: u32,
+    lessons: Vec<Lesson>,
+}
+
+struct Catalog {
+    openings: Vec<Opening>,
+    lessons: Vec<Lesson>,
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// Load the bundled catalogs with the user's files from `app_dir`. Runs at
+/// startup; a catalog read before then loads only the bundled files and
+/// stays in place, so the user's files are reported as ignored.
+pub fn init(app_dir: &Path) {
+    let user_dir = app_dir.join(USER_DIR);
+    if CATALOG.set(load(Some(&user_dir))).is_err() {
+        eprintln!("Catalog was read before startup, ignoring {}", user_dir.display());
+    }
+}
+
+fn catalog() -> &'static Catalog {
+    CATALOG.get_or_init(|| load(None))
+}
+
+pub fn openings() -> &'static [Opening] {
+    &catalog().openings
+}
+
+pub fn lessons() -> &'static [Lesson] {
+    &catalog().lessons
+}
+
+fn load(user_dir: Option<&Path>) -> Catalog {
+    let mut openings = Vec::new();
+    let mut lessons = Vec::new();
+
+    add_openings(&mut openings, "openings.json (bundled)", OPENINGS_JSON);
+    add_lessons(&mut lessons, "lessons.json (bundled)", LESSONS_JSON);
+
+    if let Some(dir) = user_dir {
+        let read = |name: &str| {
+            let path = dir.join(name);
+            std::fs::read_to_string(&path).ok().map(|text| (path.display().to_string(), text))
+        };
+        if let Some((source, text)) = read("openings.json") {
+            add_openings(&mut openings, &source, &text);
+        }
+        if let Some((source, text)) = read("lessons.json") {
+            add_lessons(&mut lessons, &source, &text);
+        }
+    }
+    check_tree(&mut openings);
+
+    Catalog { openings, lessons }
+}
+
+fn add_openings(openings: &mut Vec<Opening>, source: &str, text: &str) {
+    let file: OpeningFile = match serde_json::from_str(text) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{}: not a catalog file: {}", source, e);
+            return;
+        }
+    };
+    if file.version == 0 || file.version > OPENINGS_VERSION {
+        eprintln!("{}: catalog version {} is not supported", source, file.version);
+        return;
+    }
+
+    for mut opening in file.openings {
+        if opening.id.is_empty() {
+            opening.id = slug(&opening.name);
+        }
+        if let Err(e) = check_opening(&opening) {
+            eprintln!("{}: skipped opening {}: {}", source, opening.name, e);
+            continue;
+        }
+        match openings.iter_mut().find(|o| o.id == opening.id || o.name == opening.name) {
+            Some(existing) => *existing = opening,
+            None => openings.push(opening),
+        }
+    }
+}
+
+fn add_lessons(lessons: &mut Vec<Lesson>, source: &str, text: &str) {
+    let file: LessonFile = match serde_json::from_str(text) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{}: not a catalog file: {}", source, e);
+            return;
+        }
+    };
+    if file.version != LESSONS_VERSION {
+        eprintln!("{}: catalog version {} is not supported", source, file.version);
+        return;
+    }
+
+    for lesson in file.lessons {
+        if let Err(e) = check_lesson(&lesson) {
+            eprintln!("{}: skipped lesson {}: {}", source, lesson.id, e);
+            continue;
+        }
+        match lessons.iter_mut().find(|l| l.id == lesson.id) {
+            Some(existing) => *existing = lesson,
+            None => lessons.push(lesson),
+        }
+    }
+}
+
+/// The moves must be legal from the initial position and `pgn` must spell
+/// out the same moves
+fn check_opening(opening: &Opening) -> Result<(), String> {
+    if opening.moves.is_empty() {
+        return Err("no moves".to_string());
+    }
+    let moves = normalize(&opening.moves)?;
+
+    let pgn_moves: Vec<String> = opening
+        .pgn
+        .split_whitespace()
+        .filter(|t| !t.ends_with('.'))
+        .map(|t| t.to_string())
+        .collect();
+    let pgn_moves = normalize(&pgn_moves).map_err(|e| format!("pgn: {}", e))?;
+    if pgn_moves != moves {
+        return Err(format!("pgn \"{}\" does not match the moves", opening.pgn));
+    }
+    Ok(())
+}
+
+/// Drop variations whose parent is missing or whose line does not continue
+/// the parent's. Repeats until nothing changes, since dropping an opening
+/// orphans its own variations.
+fn check_tree(openings: &mut Vec<Opening>) {
+    let keys: HashMap<String, Vec<u64>> = openings.iter().map(|o| (o.id.clone(), line_keys(&o.moves))).collect();
+    loop {
+        let ids: HashMap<&str, &Opening> = openings.iter().map(|o| (o.id.as_str(), o)).collect();
+        let orphans: Vec<String> = openings
+            .iter()
+            .filter_map(|o| {
+                let parent = o.parent.as_deref()?;
+                let error = match ids.get(parent) {
+                    None => format!("parent {} is missing", parent),
+                    Some(p) if p.moves.len() >= o.moves.len() => format!("line is not longer than {}", p.name),
+                    Some(p) => {
+                        let end = keys[&p.id].last();
+                        if keys[&o.id].iter().any(|k| Some(k) == end) {
+                            return None;
+                        }
+                        format!("line does not pass through {}", p.name)
+                    }
+                };
+                eprintln!("catalog: skipped opening {}: {}", o.name, error);
+                Some(o.id.clone())
+            })
+            .collect();
+        if orphans.is_empty() {
+            return;
+        }
+        openings.retain(|o| !orphans.contains(&o.id));
+    }
+}
+
+/// Identifier made from an opening's name, e.g. "kings-gambit"
+fn slug(name: &str) -> String {
+    name.to_lowercase()
+        .replace(['\'', '’'], "")
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Keys of the positions after each move of a checked line; catalogs are
+/// checked when loaded, so the walk simply stops at an illegal move
+pub(crate) fn line_keys(moves: &[String]) -> Vec<u64> {
+    let mut pos = Chess::default();
+    let mut keys = Vec::with_capacity(moves.len());
+    for san in moves {
+        let Some(m) = SanPlus::from_ascii(san.as_bytes()).ok().and_then(|s| s.san.to_move(&pos).ok()) else {
+            break;
+        };
+        pos.play_unchecked(&m);
+        keys.push(polyglot::key(&pos));
+    }
+    keys
+}
+
+/// Play SAN moves from the initial position, returning them in standard form
+fn normalize(moves: &[String]) -> Result<Vec<String>, String> {
+    let mut pos = Chess::default();
+    let mut normalized = Vec::with_capacity(moves.len());
+    for (ply, text) in moves.iter().enumerate() {
+        let m = SanPlus::from_ascii(text.as_bytes())
+            .ok()
+            .and_then(|s| s.san.to_move(&pos).ok())
+            .ok_or_else(|| format!("illegal move {} at ply {}", text, ply + 1))?;
+        normalized.push(SanPlus::from_move(pos.clone(), &m).to_string());
+        pos.play_unchecked(&m);
+    }
+    Ok(normalized)
+}
+
+fn check_lesson(lesson: &Lesson) -> Result<(), String> {
+    for section in &lesson.content {
+        if let Some(fen) = &section.fen {
+            analysis::parse_fen(fen).map_err(|e| format!("section \"{}\": {}", section.title, e))?;
+        }
+    }
+    Ok(())
+}
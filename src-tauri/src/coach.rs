@@ -1,13 +1,104 @@
+use crate::models::{CoachLevel, CoachStyle, GameAnalysis, GameReport};
 use std::process::Command;
 
+/// Provider that generates coaching text
+pub const PROVIDER: &str = "gemini-cli";
+
+/// Bump whenever the comment prompt changes so cached comments are regenerated
+pub const PROMPT_VERSION: u32 = 3;
+/// Same for the key-moment batch prompt, which is versioned on its own
+pub const BATCH_PROMPT_VERSION: u32 = 1;
+
+/// Prompt a cached comment was written with. Its version is part of the
+/// cache key, so comments from another prompt are never served.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Prompt {
+    /// Old per-game comments, written before prompts were versioned
+    Legacy,
+    /// The single-move prompt
+    Move,
+    /// The key-moment batch prompt
+    Batch,
+}
+
+impl Prompt {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Legacy => "legacy",
+            Self::Move => "move",
+            Self::Batch => "batch",
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        match self {
+            Self::Legacy => 0,
+            Self::Move => PROMPT_VERSION,
+            Self::Batch => BATCH_PROMPT_VERSION,
+        }
+    }
+}
+
+/// Everything that determines a coaching comment. Comments are cached by the
+/// hash of this key, so games that reach the same position share them; the
+/// side to move comes from the position and the move number is left out.
+#[derive(Debug, Clone)]
+pub struct CommentKey {
+    pub fen: String,
+    pub played_move: String,
+    pub best_move: Option<String>,
+    pub classification: String,
+    pub model: String,
+    pub style: CoachStyle,
+    pub prompt: Prompt,
+}
+
+impl CommentKey {
+    /// Stable hex hash over the position, move, analysis and provider settings
+    pub fn hash(&self) -> String {
+        let parts = [
+            self.fen.as_str(),
+            self.played_move.as_str(),
+            self.best_move.as_deref().unwrap_or(""),
+            self.classification.as_str(),
+            PROVIDER,
+            self.model.as_str(),
+            self.style.language.code(),
+            self.style.level.code(),
+            self.prompt.code(),
+            &self.prompt.version().to_string(),
+        ];
+        format!("{:016x}", fnv1a64(&parts.join("\u{1f}")))
+    }
+}
+
+/// FNV-1a, used instead of `DefaultHasher` because cache keys must stay stable
+/// across Rust releases
+fn fnv1a64(data: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in data.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Side to move in a FEN, as written in the prompts
+fn side_to_move(fen: &str) -> &'static str {
+    if fen.split_whitespace().nth(1) == Some("b") {
+        "black"
+    } else {
+        "white"
+    }
+}
+
 /// Build a structured chess coaching prompt
 fn build_coach_prompt(
     fen: &str,
     played_move: &str,
     best_move: Option<&str>,
     classification: &str,
-    color: &str,
-    move_number: u32,
+    style: &CoachStyle,
 ) -> String {
     let context = match classification {
         "Blunder" => "This was a BLUNDER — a serious mistake that significantly worsens the position.",
@@ -27,47 +118,252 @@ fn build_coach_prompt(
         r#"You are a friendly chess coach helping a player improve. Analyze this specific moment:
 
 Position (FEN): {}
-Move played: {}. {} ({})
+Move played: {} ({})
 {}Classification: {}
 
 {}
 
-Give a short, educational explanation (2-3 sentences max) in a warm coaching tone. Focus on:
+Give a short, educational explanation (2-3 sentences max) {}. Focus on:
 - WHY the played move is {} (what does it miss or achieve?)
 - WHAT the better alternative does (if applicable)
 - A practical TIP the player can remember
 
-Keep it concise, specific to this position, and avoid generic advice. Do NOT include the FEN or move notation in your response — the player already sees those. Do NOT use markdown formatting."#,
+Keep it concise, specific to this position, and avoid generic advice. Do NOT include the FEN or move notation in your response — the player already sees those. Do NOT use markdown formatting. {}"#,
         fen,
-        move_number,
         played_move,
-        color,
+        side_to_move(fen),
         best_info,
         classification,
         context,
+        persona(style.level),
         classification.to_lowercase(),
+        language_instruction(style),
     )
 }
 
+/// Tone and vocabulary for the player's level
+fn persona(level: CoachLevel) -> &'static str {
+    match level {
+        CoachLevel::Beginner => {
+            "in a warm, encouraging tone for a beginner. Use simple words, explain any chess term you use, and avoid engine jargon"
+        }
+        CoachLevel::Intermediate => "in a warm coaching tone",
+        CoachLevel::Advanced => {
+            "in a concise, technical tone for an experienced club player. Concrete lines and strategic concepts are welcome"
+        }
+    }
+}
+
+fn language_instruction(style: &CoachStyle) -> String {
+    format!("Write your answer in {}.", style.language.name())
+}
+
 /// Call Gemini CLI to get coaching comment
+pub fn get_coaching_comment(key: &CommentKey) -> Result<String, String> {
+    let prompt = build_coach_prompt(
+        &key.fen,
+        &key.played_move,
+        key.best_move.as_deref(),
+        &key.classification,
+        &key.style,
+    );
+    run_gemini(&prompt, &key.model)
+}
+
+/// A key moment sent to the coach as part of a batch request
+#[derive(Debug, Clone)]
+pub struct BatchItem {
+    pub move_index: usize,
+    pub key: CommentKey,
+}
+
+/// Build one prompt covering several moves, answered as a JSON array
+fn build_batch_prompt(items: &[BatchItem], style: &CoachStyle) -> String {
+    let moments = items
+        .iter()
+        .map(|item| {
+            format!(
+                "- move_index {}: {} ({}), classification {}, best move {}, position before the move (FEN): {}",
+                item.move_index,
+                item.key.played_move,
+                side_to_move(&item.key.fen),
+                item.key.classification,
+                item.key.best_move.as_deref().unwrap_or("n/a"),
+                item.key.fen,
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        r#"You are a friendly chess coach helping a player improve. These are the key moments of one game:
+
+{}
+
+For EACH moment give a short, educational explanation (2-3 sentences max) {}. Explain why the move deserves its classification, what the better alternative does (if any), and one practical tip. Do NOT include the FEN in your response. {}
+
+Respond with ONLY a JSON array, no markdown and no extra text, with one object per moment:
+[{{"move_index": <number>, "comment": "..."}}]"#,
+        moments,
+        persona(style.level),
+        language_instruction(style),
+    )
+}
+
+/// Ask Gemini CLI for comments on several moves at once.
+/// Returns (move_index, comment) pairs; moves the response skipped are left out.
+pub fn get_batch_comments(
+    items: &[BatchItem],
+    model: &str,
+    style: &CoachStyle,
+) -> Result<Vec<(usize, String)>, String> {
+    let prompt = build_batch_prompt(items, style);
+    let response = run_gemini(&prompt, model)?;
+    parse_batch_comments(&response, items)
+}
+
+#[derive(serde::Deserialize)]
+struct BatchComment {
+    move_index: usize,
+    comment: String,
+}
+
+fn parse_batch_comments(response: &str, items: &[BatchItem]) -> Result<Vec<(usize, String)>, String> {
+    let start = response.find('[');
+    let end = response.rfind(']');
+    let json = match (start, end) {
+        (Some(s), Some(e)) if s < e => &response[s..=e],
+        _ => return Err("Coach response did not contain a JSON array".to_string()),
+    };
+
+    let parsed: Vec<BatchComment> = serde_json::from_str(json)
+        .map_err(|e| format!("Failed to parse batch comments: {}", e))?;
+
+    let mut comments: Vec<(usize, String)> = Vec::new();
+    for c in parsed {
+        let requested = items.iter().any(|i| i.move_index == c.move_index);
+        let duplicate = comments.iter().any(|(idx, _)| *idx == c.move_index);
+        if requested && !duplicate && !c.comment.trim().is_empty() {
+            comments.push((c.move_index, c.comment.trim().to_string()));
+        }
+    }
+    Ok(comments)
+}
+
+/// Build a whole-game review prompt that asks for a JSON-only answer
+fn build_report_prompt(analysis: &GameAnalysis, style: &CoachStyle) -> String {
+    let summary = &analysis.summary;
+    let opening = summary.opening_name.as_deref().unwrap_or("Unknown");
+
+    let moments = if analysis.key_moments.is_empty() {
+        "None".to_string()
+    } else {
+        analysis
+            .key_moments
+            .iter()
+            .map(|k| format!("- {} ({}, {}): {}", k.san, k.color, k.classification, k.description))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    // Compact eval trajectory: one entry per move, e.g. "12... Nxe5 -1.40"
+    let trajectory = analysis
+        .moves
+        .iter()
+        .map(|m| {
+            let dots = if m.color == "white" { "." } else { "..." };
+            format!("{}{} {} {:+.2}", m.move_number, dots, m.san, m.eval_score)
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        r#"You are a friendly chess coach writing a post-game review for a player.
+
+Game: {} (White) vs {} (Black), result {}, {} {}
+Opening: {}
+Summary: {} moves, accuracy {:.1}%, {} brilliant, {} great, {} inaccuracies, {} mistakes, {} blunders
+
+Key moments:
+{}
+
+Evaluation after each move (pawns, positive = White is better):
+{}
+
+Write a structured review of the whole game {}. {} Keep the JSON keys in English. Respond with ONLY a JSON object, no markdown and no extra text, using exactly these keys:
+{{"opening": "...", "middlegame_turning_point": "...", "endgame": "...", "takeaways": ["...", "...", "..."]}}
+
+- "opening": 2-3 sentences on how the opening went for both sides
+- "middlegame_turning_point": 2-3 sentences on the moment the game turned and why
+- "endgame": 2-3 sentences on how the game was decided (or that it ended before an endgame)
+- "takeaways": exactly 3 short, practical lessons the player can remember"#,
+        analysis.white,
+        analysis.black,
+        analysis.result,
+        analysis.time_class,
+        analysis.time_control,
+        opening,
+        summary.total_moves,
+        summary.accuracy,
+        summary.brilliancies,
+        summary.great_moves,
+        summary.inaccuracies,
+        summary.mistakes,
+        summary.blunders,
+        moments,
+        trajectory,
+        persona(style.level),
+        language_instruction(style),
+    )
+}
+
+/// Ask Gemini CLI for a whole-game review
+pub fn get_game_report(analysis: &GameAnalysis, model: &str, style: &CoachStyle) -> Result<GameReport, String> {
+    let prompt = build_report_prompt(analysis, style);
+    let response = run_gemini(&prompt, model)?;
+    let mut report = parse_game_report(&response)?;
+    report.style = *style;
+    Ok(report)
+}
+
+/// Parse the JSON review, tolerating code fences or chatter around the object
+fn parse_game_report(response: &str) -> Result<GameReport, String> {
+    let start = response.find('{');
+    let end = response.rfind('}');
+    let json = match (start, end) {
+        (Some(s), Some(e)) if s < e => &response[s..=e],
+        _ => return Err("Coach response did not contain a JSON object".to_string()),
+    };
+
+    let mut report: GameReport = serde_json::from_str(json)
+        .map_err(|e| format!("Failed to parse coach report: {}", e))?;
+
+    report.takeaways.retain(|t| !t.trim().is_empty());
+    if report.takeaways.is_empty() {
+        return Err("Coach report contained no takeaways".to_string());
+    }
+    report.takeaways.truncate(3);
+
+    Ok(report)
+}
+
+/// Run a prompt through the Gemini CLI, optionally with a specific model
 /// Uses `echo "prompt" | gemini 2>/dev/null` which works with Google Pro subscription (free)
-pub fn get_coaching_comment(
-    fen: &str,
-    played_move: &str,
-    best_move: Option<&str>,
-    classification: &str,
-    color: &str,
-    move_number: u32,
-) -> Result<String, String> {
-    let prompt = build_coach_prompt(fen, played_move, best_move, classification, color, move_number);
+fn run_gemini(prompt: &str, model: &str) -> Result<String, String> {
+    let model_arg = if model.is_empty() {
+        String::new()
+    } else {
+        format!(" -m {}", shell_escape(model))
+    };
 
     // Run: echo "prompt" | gemini 2>/dev/null
     // The gemini CLI reads from stdin when piped and outputs to stdout
     let output = Command::new("sh")
         .arg("-c")
         .arg(format!(
-            r#"echo {} | gemini 2>/dev/null"#,
-            shell_escape(&prompt)
+            r#"echo {} | gemini{} 2>/dev/null"#,
+            shell_escape(prompt),
+            model_arg
         ))
         .output()
         .map_err(|e| format!("Failed to run gemini CLI: {}", e))?;
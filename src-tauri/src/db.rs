@@ -1,5 +1,13 @@
-use crate::models::ChessComGame;
+use crate::coach::{self, CommentKey};
+use crate::explorer::GameMove;
+use crate::models::{
+    ChessComGame, CoachStyle, DeviationKind, DrillStats, ExplorerFilter, ExplorerGame, ExplorerMove, ForgottenLine, GameAnalysis, Puzzle, RepertoireDeviation, RepertoireMove, ReviewGrade,
+    ReviewItem, ReviewKind, ReviewStats,
+};
+use crate::repertoire::Edge;
+use crate::review;
 use rusqlite::{Connection, params};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Mutex;
 
@@ -49,21 +57,145 @@ impl Database {
                 value TEXT NOT NULL
             );
 
-            CREATE TABLE IF NOT EXISTS coach_comments (
+            CREATE TABLE IF NOT EXISTS coach_cache (
+                cache_key TEXT PRIMARY KEY,
+                fen TEXT NOT NULL,
+                played_move TEXT NOT NULL,
+                classification TEXT NOT NULL,
+                provider TEXT NOT NULL,
+                model TEXT NOT NULL,
+                prompt_version INTEGER NOT NULL,
+                comment TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            );
+
+            CREATE TABLE IF NOT EXISTS coach_comment_refs (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 game_url TEXT NOT NULL,
                 move_index INTEGER NOT NULL,
-                comment TEXT NOT NULL,
+                cache_key TEXT NOT NULL,
+                UNIQUE(game_url, move_index)
+            );
+
+            CREATE TABLE IF NOT EXISTS game_reports (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                game_url TEXT NOT NULL UNIQUE,
+                report_json TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            );
+
+            CREATE TABLE IF NOT EXISTS puzzles (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                game_url TEXT NOT NULL,
+                move_index INTEGER NOT NULL,
+                fen TEXT NOT NULL,
+                color TEXT NOT NULL,
+                played_move TEXT NOT NULL,
+                solution TEXT NOT NULL,
+                solution_san TEXT NOT NULL,
+                themes TEXT NOT NULL,
+                mate_in INTEGER,
+                rating INTEGER NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                solved INTEGER NOT NULL DEFAULT 0,
                 created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
                 UNIQUE(game_url, move_index)
             );
 
+            CREATE TABLE IF NOT EXISTS review_items (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                kind TEXT NOT NULL,
+                source_id TEXT NOT NULL,
+                title TEXT NOT NULL,
+                fen TEXT NOT NULL,
+                solution TEXT NOT NULL,
+                ease REAL NOT NULL,
+                interval_days INTEGER NOT NULL DEFAULT 0,
+                repetitions INTEGER NOT NULL DEFAULT 0,
+                lapses INTEGER NOT NULL DEFAULT 0,
+                due_at INTEGER NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE(kind, source_id)
+            );
+
+            CREATE TABLE IF NOT EXISTS review_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                item_id INTEGER NOT NULL,
+                grade TEXT NOT NULL,
+                interval_before INTEGER NOT NULL,
+                interval_after INTEGER NOT NULL,
+                ease_after REAL NOT NULL,
+                reviewed_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS repertoire_moves (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                color TEXT NOT NULL,
+                position_key INTEGER NOT NULL,
+                fen TEXT NOT NULL,
+                san TEXT NOT NULL,
+                uci TEXT NOT NULL,
+                next_key INTEGER NOT NULL,
+                fen_after TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE(color, position_key, uci)
+            );
+
+            CREATE TABLE IF NOT EXISTS repertoire_notes (
+                color TEXT NOT NULL,
+                position_key INTEGER NOT NULL,
+                fen TEXT NOT NULL,
+                note TEXT NOT NULL,
+                PRIMARY KEY(color, position_key)
+            );
+
+            CREATE TABLE IF NOT EXISTS positions (
+                game_url TEXT NOT NULL,
+                ply INTEGER NOT NULL,
+                position_key INTEGER NOT NULL,
+                san TEXT NOT NULL,
+                uci TEXT NOT NULL,
+                PRIMARY KEY(game_url, ply)
+            );
+
+            CREATE TABLE IF NOT EXISTS indexed_games (
+                game_url TEXT PRIMARY KEY
+            );
+
+            CREATE TABLE IF NOT EXISTS drill_stats (
+                line_id TEXT PRIMARY KEY,
+                moves_played INTEGER NOT NULL DEFAULT 0,
+                moves_correct INTEGER NOT NULL DEFAULT 0,
+                completions INTEGER NOT NULL DEFAULT 0,
+                last_drilled INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS repertoire_deviations (
+                game_url TEXT PRIMARY KEY,
+                color TEXT NOT NULL,
+                position_key INTEGER NOT NULL,
+                fen TEXT NOT NULL,
+                line TEXT NOT NULL,
+                played TEXT NOT NULL,
+                user_error INTEGER NOT NULL,
+                date TEXT NOT NULL
+            );
+
             CREATE INDEX IF NOT EXISTS idx_games_username ON games(username);
             CREATE INDEX IF NOT EXISTS idx_games_end_time ON games(end_time);
             CREATE INDEX IF NOT EXISTS idx_games_url ON games(url);
-            CREATE INDEX IF NOT EXISTS idx_coach_game ON coach_comments(game_url);",
+            CREATE INDEX IF NOT EXISTS idx_coach_refs_game ON coach_comment_refs(game_url);
+            CREATE INDEX IF NOT EXISTS idx_coach_refs_key ON coach_comment_refs(cache_key);
+            CREATE INDEX IF NOT EXISTS idx_puzzles_game ON puzzles(game_url);
+            CREATE INDEX IF NOT EXISTS idx_review_items_due ON review_items(due_at);
+            CREATE INDEX IF NOT EXISTS idx_review_log_time ON review_log(reviewed_at);
+            CREATE INDEX IF NOT EXISTS idx_repertoire_position ON repertoire_moves(color, position_key);
+            CREATE INDEX IF NOT EXISTS idx_repertoire_next ON repertoire_moves(color, next_key);
+            CREATE INDEX IF NOT EXISTS idx_positions_key ON positions(position_key);
+            CREATE INDEX IF NOT EXISTS idx_repertoire_deviations_position ON repertoire_deviations(color, position_key);",
         )
         .map_err(|e| format!("Failed to create tables: {}", e))?;
+        migrate_coach_comments(&conn)?;
 
         Ok(Self {
             conn: Mutex::new(conn),
@@ -117,31 +249,29 @@ impl Database {
             .map_err(|e| format!("Query error: {}", e))?;
 
         let games = stmt
-            .query_map(params![username.to_lowercase(), limit as i64], |row| {
-                use crate::models::ChessComPlayer;
-
-                Ok(ChessComGame {
-                    url: row.get(0)?,
-                    pgn: row.get(1)?,
-                    time_control: row.get(2)?,
-                    end_time: row.get::<_, Option<i64>>(3)?.map(|v| v as u64),
-                    rated: row.get::<_, Option<i32>>(4)?.map(|v| v != 0),
-                    time_class: row.get(5)?,
-                    rules: row.get(6)?,
-                    white: ChessComPlayer {
-                        username: row.get(7)?,
-                        rating: row.get::<_, Option<i32>>(8)?.map(|v| v as u32),
-                        result: row.get(9)?,
-                        id: None,
-                    },
-                    black: ChessComPlayer {
-                        username: row.get(10)?,
-                        rating: row.get::<_, Option<i32>>(11)?.map(|v| v as u32),
-                        result: row.get(12)?,
-                        id: None,
-                    },
-                })
-            })
+            .query_map(params![username.to_lowercase(), limit as i64], game_from_row)
+            .map_err(|e| format!("Query map error: {}", e))?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(games)
+    }
+
+    /// Every standard chess game of a user, with its cached analysis if there is one
+    pub fn get_games_with_analysis(&self, username: &str) -> Result<Vec<(ChessComGame, Option<String>)>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT g.url, g.pgn, g.time_control, g.end_time, g.rated, g.time_class, g.rules, g.white_username, g.white_rating, g.white_result, g.black_username, g.black_rating, g.black_result, a.analysis_json
+                 FROM games g LEFT JOIN analysis_cache a ON a.game_url = g.url
+                 WHERE g.username = ?1 AND (g.rules IS NULL OR g.rules = 'chess')
+                 ORDER BY g.end_time DESC",
+            )
+            .map_err(|e| format!("Query error: {}", e))?;
+
+        let games = stmt
+            .query_map(params![username.to_lowercase()], |row| Ok((game_from_row(row)?, row.get(13)?)))
             .map_err(|e| format!("Query map error: {}", e))?
             .filter_map(|r| r.ok())
             .collect();
@@ -229,21 +359,44 @@ impl Database {
         Ok(())
     }
 
-    pub fn save_coach_comment(&self, game_url: &str, move_index: usize, comment: &str) -> Result<(), String> {
+    /// Store a coaching comment under its content key and link it to a game move
+    pub fn save_coach_comment(
+        &self,
+        key: &CommentKey,
+        game_url: &str,
+        move_index: usize,
+        comment: &str,
+    ) -> Result<(), String> {
         let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let cache_key = key.hash();
         conn.execute(
-            "INSERT OR REPLACE INTO coach_comments (game_url, move_index, comment) VALUES (?1, ?2, ?3)",
-            params![game_url, move_index as i64, comment],
+            "INSERT OR REPLACE INTO coach_cache (cache_key, fen, played_move, classification, provider, model, prompt_version, comment)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                cache_key,
+                key.fen,
+                key.played_move,
+                key.classification,
+                coach::PROVIDER,
+                key.model,
+                key.prompt.version(),
+                comment,
+            ],
         )
         .map_err(|e| format!("Failed to save coach comment: {}", e))?;
+        conn.execute(
+            "INSERT OR REPLACE INTO coach_comment_refs (game_url, move_index, cache_key) VALUES (?1, ?2, ?3)",
+            params![game_url, move_index as i64, cache_key],
+        )
+        .map_err(|e| format!("Failed to link coach comment: {}", e))?;
         Ok(())
     }
 
-    pub fn get_coach_comment(&self, game_url: &str, move_index: usize) -> Result<Option<String>, String> {
+    pub fn get_coach_comment(&self, key: &CommentKey) -> Result<Option<String>, String> {
         let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
         let result = conn.query_row(
-            "SELECT comment FROM coach_comments WHERE game_url = ?1 AND move_index = ?2",
-            params![game_url, move_index as i64],
+            "SELECT comment FROM coach_cache WHERE cache_key = ?1",
+            params![key.hash()],
             |row| row.get::<_, String>(0),
         );
         match result {
@@ -252,4 +405,807 @@ impl Database {
             Err(e) => Err(format!("Query error: {}", e)),
         }
     }
+
+    /// Record that a game move uses an already cached comment
+    pub fn link_coach_comment(&self, key: &CommentKey, game_url: &str, move_index: usize) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        conn.execute(
+            "INSERT OR REPLACE INTO coach_comment_refs (game_url, move_index, cache_key) VALUES (?1, ?2, ?3)",
+            params![game_url, move_index as i64, key.hash()],
+        )
+        .map_err(|e| format!("Failed to link coach comment: {}", e))?;
+        Ok(())
+    }
+
+    /// Delete cached coaching comments for one game, or all of them.
+    /// Comments shared with other games are removed too, since they are stale
+    /// for every game that references them.
+    pub fn purge_coach_comments(&self, game_url: Option<&str>) -> Result<usize, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let purged = match game_url {
+            Some(url) => {
+                let n = conn
+                    .execute(
+                        "DELETE FROM coach_cache WHERE cache_key IN
+                         (SELECT cache_key FROM coach_comment_refs WHERE game_url = ?1)",
+                        params![url],
+                    )
+                    .map_err(|e| format!("Failed to purge coach comments: {}", e))?;
+                conn.execute(
+                    "DELETE FROM coach_comment_refs WHERE cache_key NOT IN (SELECT cache_key FROM coach_cache)",
+                    [],
+                )
+                .map_err(|e| format!("Failed to purge coach comments: {}", e))?;
+                n
+            }
+            None => {
+                let n = conn
+                    .execute("DELETE FROM coach_cache", [])
+                    .map_err(|e| format!("Failed to purge coach comments: {}", e))?;
+                conn.execute("DELETE FROM coach_comment_refs", [])
+                    .map_err(|e| format!("Failed to purge coach comments: {}", e))?;
+                n
+            }
+        };
+        Ok(purged)
+    }
+
+    pub fn save_game_report(&self, game_url: &str, report_json: &str) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        conn.execute(
+            "INSERT OR REPLACE INTO game_reports (game_url, report_json) VALUES (?1, ?2)",
+            params![game_url, report_json],
+        )
+        .map_err(|e| format!("Failed to save game report: {}", e))?;
+        Ok(())
+    }
+
+    pub fn get_game_report(&self, game_url: &str) -> Result<Option<String>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let result = conn.query_row(
+            "SELECT report_json FROM game_reports WHERE game_url = ?1",
+            params![game_url],
+            |row| row.get::<_, String>(0),
+        );
+        match result {
+            Ok(json) => Ok(Some(json)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(format!("Query error: {}", e)),
+        }
+    }
+
+    /// The account a stored game was fetched for
+    pub fn get_game_username(&self, game_url: &str) -> Result<Option<String>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let result = conn.query_row(
+            "SELECT username FROM games WHERE url = ?1",
+            params![game_url],
+            |row| row.get::<_, String>(0),
+        );
+        match result {
+            Ok(username) => Ok(Some(username)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(format!("Query error: {}", e)),
+        }
+    }
+
+    /// Store new puzzles; positions already extracted from a game are kept as they are
+    pub fn save_puzzles(&self, puzzles: &[Puzzle]) -> Result<usize, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let mut saved = 0;
+
+        for p in puzzles {
+            let n = conn
+                .execute(
+                    "INSERT OR IGNORE INTO puzzles (game_url, move_index, fen, color, played_move, solution, solution_san, themes, mate_in, rating)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    params![
+                        p.game_url,
+                        p.move_index as i64,
+                        p.fen,
+                        p.color,
+                        p.played_move,
+                        serde_json::to_string(&p.solution).unwrap_or_default(),
+                        serde_json::to_string(&p.solution_san).unwrap_or_default(),
+                        serde_json::to_string(&p.themes).unwrap_or_default(),
+                        p.mate_in,
+                        p.rating,
+                    ],
+                )
+                .map_err(|e| format!("Failed to save puzzle: {}", e))?;
+            saved += n;
+        }
+
+        Ok(saved)
+    }
+
+    /// Puzzles from one game, or from all games, easiest first. `None` returns
+    /// every match.
+    pub fn get_puzzles(&self, game_url: Option<&str>, limit: Option<usize>) -> Result<Vec<Puzzle>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let limit = limit.map(|n| format!(" LIMIT {}", n)).unwrap_or_default();
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT id, game_url, move_index, fen, color, played_move, solution, solution_san, themes, mate_in, rating, attempts, solved
+                 FROM puzzles WHERE ?1 IS NULL OR game_url = ?1 ORDER BY rating, id{}",
+                limit
+            ))
+            .map_err(|e| format!("Query error: {}", e))?;
+
+        let puzzles = stmt
+            .query_map(params![game_url], puzzle_from_row)
+            .map_err(|e| format!("Query map error: {}", e))?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(puzzles)
+    }
+
+    pub fn get_puzzle(&self, id: i64) -> Result<Option<Puzzle>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let result = conn.query_row(
+            "SELECT id, game_url, move_index, fen, color, played_move, solution, solution_san, themes, mate_in, rating, attempts, solved
+             FROM puzzles WHERE id = ?1",
+            params![id],
+            puzzle_from_row,
+        );
+        match result {
+            Ok(puzzle) => Ok(Some(puzzle)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(format!("Query error: {}", e)),
+        }
+    }
+
+    /// Count a finished attempt at a puzzle
+    pub fn record_puzzle_attempt(&self, id: i64, solved: bool) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        conn.execute(
+            "UPDATE puzzles SET attempts = attempts + 1, solved = solved + ?2 WHERE id = ?1",
+            params![id, solved as i32],
+        )
+        .map_err(|e| format!("Failed to record puzzle attempt: {}", e))?;
+        Ok(())
+    }
+
+    /// Add a position to the review schedule, due right away. Returns `None`
+    /// when the same source is already scheduled.
+    pub fn add_review_item(
+        &self,
+        kind: ReviewKind,
+        source_id: &str,
+        title: &str,
+        fen: &str,
+        solution: &[String],
+        now: i64,
+    ) -> Result<Option<i64>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let n = conn
+            .execute(
+                "INSERT OR IGNORE INTO review_items (kind, source_id, title, fen, solution, ease, due_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    kind.code(),
+                    source_id,
+                    title,
+                    fen,
+                    serde_json::to_string(solution).unwrap_or_default(),
+                    review::INITIAL_EASE,
+                    now,
+                ],
+            )
+            .map_err(|e| format!("Failed to add review item: {}", e))?;
+        Ok((n > 0).then(|| conn.last_insert_rowid()))
+    }
+
+    /// Items due at `now`, most overdue first
+    pub fn get_due_reviews(&self, now: i64, limit: usize) -> Result<Vec<ReviewItem>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, kind, source_id, title, fen, solution, ease, interval_days, repetitions, lapses, due_at
+                 FROM review_items WHERE due_at <= ?1 ORDER BY due_at LIMIT ?2",
+            )
+            .map_err(|e| format!("Query error: {}", e))?;
+
+        let items = stmt
+            .query_map(params![now, limit as i64], review_item_from_row)
+            .map_err(|e| format!("Query map error: {}", e))?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(items)
+    }
+
+    pub fn get_review_item(&self, id: i64) -> Result<Option<ReviewItem>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let result = conn.query_row(
+            "SELECT id, kind, source_id, title, fen, solution, ease, interval_days, repetitions, lapses, due_at
+             FROM review_items WHERE id = ?1",
+            params![id],
+            review_item_from_row,
+        );
+        match result {
+            Ok(item) => Ok(Some(item)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(format!("Query error: {}", e)),
+        }
+    }
+
+    /// Store the rescheduled item and log the review
+    pub fn save_review(&self, before: &ReviewItem, after: &ReviewItem, grade: ReviewGrade, now: i64) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        conn.execute(
+            "UPDATE review_items SET ease = ?2, interval_days = ?3, repetitions = ?4, lapses = ?5, due_at = ?6 WHERE id = ?1",
+            params![after.id, after.ease, after.interval_days, after.repetitions, after.lapses, after.due_at],
+        )
+        .map_err(|e| format!("Failed to update review item: {}", e))?;
+        conn.execute(
+            "INSERT INTO review_log (item_id, grade, interval_before, interval_after, ease_after, reviewed_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                after.id,
+                grade.code(),
+                before.interval_days,
+                after.interval_days,
+                after.ease,
+                now,
+            ],
+        )
+        .map_err(|e| format!("Failed to log review: {}", e))?;
+        Ok(())
+    }
+
+    /// Review counts for the day running from `day_start` to `day_end`
+    pub fn get_review_stats(&self, now: i64, day_start: i64, day_end: i64) -> Result<ReviewStats, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let count = |sql: &str, args: &[&dyn rusqlite::ToSql]| -> Result<u32, String> {
+            conn.query_row(sql, args, |row| row.get::<_, i64>(0))
+                .map(|n| n as u32)
+                .map_err(|e| format!("Query error: {}", e))
+        };
+
+        Ok(ReviewStats {
+            total_items: count("SELECT COUNT(*) FROM review_items", &[])?,
+            due_now: count("SELECT COUNT(*) FROM review_items WHERE due_at <= ?1", &[&now])?,
+            due_today: count("SELECT COUNT(*) FROM review_items WHERE due_at < ?1", &[&day_end])?,
+            reviewed_today: count(
+                "SELECT COUNT(*) FROM review_log WHERE reviewed_at >= ?1 AND reviewed_at < ?2",
+                &[&day_start, &day_end],
+            )?,
+            failed_today: count(
+                "SELECT COUNT(*) FROM review_log WHERE reviewed_at >= ?1 AND reviewed_at < ?2 AND grade = 'again'",
+                &[&day_start, &day_end],
+            )?,
+        })
+    }
+
+    /// Add moves to a repertoire, skipping ones already there
+    pub fn add_repertoire_moves(&self, color: &str, edges: &[Edge]) -> Result<usize, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let mut added = 0;
+        for e in edges {
+            added += conn
+                .execute(
+                    "INSERT OR IGNORE INTO repertoire_moves (color, position_key, fen, san, uci, next_key, fen_after)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![color, e.key as i64, e.fen, e.san, e.uci, e.next_key as i64, e.fen_after],
+                )
+                .map_err(|e| format!("Failed to add repertoire move: {}", e))?;
+        }
+        Ok(added)
+    }
+
+    /// Remove a move and everything below it that no other line still reaches.
+    /// Returns how many moves were deleted.
+    pub fn remove_repertoire_move(&self, color: &str, key: u64, uci: &str) -> Result<usize, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let next: Option<i64> = conn
+            .query_row(
+                "SELECT next_key FROM repertoire_moves WHERE color = ?1 AND position_key = ?2 AND uci = ?3",
+                params![color, key as i64, uci],
+                |row| row.get(0),
+            )
+            .ok();
+        let Some(next) = next else {
+            return Ok(0);
+        };
+        let mut removed = conn
+            .execute(
+                "DELETE FROM repertoire_moves WHERE color = ?1 AND position_key = ?2 AND uci = ?3",
+                params![color, key as i64, uci],
+            )
+            .map_err(|e| format!("Failed to remove repertoire move: {}", e))?;
+
+        let mut orphans = vec![next];
+        while let Some(k) = orphans.pop() {
+            let reached: i64 = conn
+                .query_row(
+                    "SELECT COUNT(*) FROM repertoire_moves WHERE color = ?1 AND next_key = ?2",
+                    params![color, k],
+                    |row| row.get(0),
+                )
+                .map_err(|e| format!("Query error: {}", e))?;
+            if reached > 0 {
+                continue;
+            }
+            let mut stmt = conn
+                .prepare("SELECT next_key FROM repertoire_moves WHERE color = ?1 AND position_key = ?2")
+                .map_err(|e| format!("Query error: {}", e))?;
+            let children: Vec<i64> = stmt
+                .query_map(params![color, k], |row| row.get(0))
+                .map_err(|e| format!("Query map error: {}", e))?
+                .filter_map(|r| r.ok())
+                .collect();
+            removed += conn
+                .execute(
+                    "DELETE FROM repertoire_moves WHERE color = ?1 AND position_key = ?2",
+                    params![color, k],
+                )
+                .map_err(|e| format!("Failed to remove repertoire move: {}", e))?;
+            conn.execute(
+                "DELETE FROM repertoire_notes WHERE color = ?1 AND position_key = ?2",
+                params![color, k],
+            )
+            .map_err(|e| format!("Failed to remove repertoire note: {}", e))?;
+            orphans.extend(children);
+        }
+
+        Ok(removed)
+    }
+
+    /// Moves prepared in one position, in the order they were added
+    pub fn get_repertoire_moves(&self, color: &str, key: u64) -> Result<Vec<RepertoireMove>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, color, fen, san, uci, fen_after FROM repertoire_moves
+                 WHERE color = ?1 AND position_key = ?2 ORDER BY id",
+            )
+            .map_err(|e| format!("Query error: {}", e))?;
+
+        let moves = stmt
+            .query_map(params![color, key as i64], |row| {
+                Ok(RepertoireMove {
+                    id: row.get(0)?,
+                    color: row.get(1)?,
+                    fen: row.get(2)?,
+                    san: row.get(3)?,
+                    uci: row.get(4)?,
+                    fen_after: row.get(5)?,
+                })
+            })
+            .map_err(|e| format!("Query map error: {}", e))?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(moves)
+    }
+
+    /// The whole repertoire of one side, in the order moves were added
+    pub fn get_repertoire_edges(&self, color: &str) -> Result<Vec<Edge>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT position_key, fen, san, uci, next_key, fen_after FROM repertoire_moves
+                 WHERE color = ?1 ORDER BY id",
+            )
+            .map_err(|e| format!("Query error: {}", e))?;
+
+        let edges = stmt
+            .query_map(params![color], |row| {
+                Ok(Edge {
+                    key: row.get::<_, i64>(0)? as u64,
+                    fen: row.get(1)?,
+                    san: row.get(2)?,
+                    uci: row.get(3)?,
+                    next_key: row.get::<_, i64>(4)? as u64,
+                    fen_after: row.get(5)?,
+                })
+            })
+            .map_err(|e| format!("Query map error: {}", e))?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(edges)
+    }
+
+    /// Stored games whose moves are not in the position index yet
+    pub fn get_unindexed_games(&self, limit: usize) -> Result<Vec<(String, Option<String>)>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT url, pgn FROM games
+                 WHERE url NOT IN (SELECT game_url FROM indexed_games) AND (rules IS NULL OR rules = 'chess')
+                 LIMIT ?1",
+            )
+            .map_err(|e| format!("Query error: {}", e))?;
+
+        let games = stmt
+            .query_map(params![limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| format!("Query map error: {}", e))?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(games)
+    }
+
+    /// Add the moves of games to the position index
+    pub fn save_game_positions(&self, games: &[(String, Vec<GameMove>)]) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        // One transaction for the batch, thousands of rows are written at once
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to index games: {}", e))?;
+        for (url, moves) in games {
+            for m in moves {
+                tx.execute(
+                    "INSERT OR REPLACE INTO positions (game_url, ply, position_key, san, uci) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![url, m.ply as i64, m.key as i64, m.san, m.uci],
+                )
+                .map_err(|e| format!("Failed to index games: {}", e))?;
+            }
+            tx.execute("INSERT OR IGNORE INTO indexed_games (game_url) VALUES (?1)", params![url])
+                .map_err(|e| format!("Failed to index games: {}", e))?;
+        }
+        tx.commit().map_err(|e| format!("Failed to index games: {}", e))
+    }
+
+    /// Moves played from the position with `key` in the games `filter` allows,
+    /// most played first, each with its latest game
+    pub fn explore_position(&self, key: u64, filter: &ExplorerFilter) -> Result<Vec<ExplorerMove>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        // SQLite takes the bare columns from the row holding MAX(end_time)
+        let mut stmt = conn
+            .prepare(
+                "SELECT p.san, p.uci, COUNT(*) AS n,
+                     SUM(g.white_result = 'win'), SUM(g.black_result = 'win'),
+                     g.url, g.white_username, g.black_username, MAX(g.end_time)
+                 FROM positions p JOIN games g ON g.url = p.game_url
+                 WHERE p.position_key = ?1
+                     AND (?2 IS NULL OR g.username = ?2)
+                     AND (?3 IS NULL OR ?3 = CASE WHEN lower(g.white_username) = g.username THEN 'white' ELSE 'black' END)
+                     AND (?4 IS NULL OR g.time_class = ?4)
+                     AND (?5 IS NULL OR g.end_time >= ?5)
+                     AND (?6 IS NULL OR g.end_time <= ?6)
+                 GROUP BY p.uci
+                 ORDER BY n DESC, MAX(g.end_time) DESC",
+            )
+            .map_err(|e| format!("Query error: {}", e))?;
+
+        let moves = stmt
+            .query_map(
+                params![
+                    key as i64,
+                    filter.username.as_ref().map(|u| u.to_lowercase()),
+                    filter.color,
+                    filter.time_class,
+                    filter.since,
+                    filter.until,
+                ],
+                |row| {
+                    let games: u32 = row.get(2)?;
+                    let white_wins: u32 = row.get(3)?;
+                    let black_wins: u32 = row.get(4)?;
+                    Ok(ExplorerMove {
+                        san: row.get(0)?,
+                        uci: row.get(1)?,
+                        games,
+                        white_wins,
+                        draws: games - white_wins - black_wins,
+                        black_wins,
+                        score: 0.0,
+                        last_game: ExplorerGame {
+                            url: row.get(5)?,
+                            white: row.get(6)?,
+                            black: row.get(7)?,
+                            end_time: row.get::<_, Option<i64>>(8)?.map(|v| v as u64),
+                        },
+                    })
+                },
+            )
+            .map_err(|e| format!("Query map error: {}", e))?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(moves)
+    }
+
+    /// Count one of the user's moves in a drill of `line_id`
+    pub fn record_drill_move(&self, line_id: &str, correct: bool, finished: bool, now: i64) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        conn.execute(
+            "INSERT INTO drill_stats (line_id, moves_played, moves_correct, completions, last_drilled)
+             VALUES (?1, 1, ?2, ?3, ?4)
+             ON CONFLICT(line_id) DO UPDATE SET
+                 moves_played = moves_played + 1,
+                 moves_correct = moves_correct + excluded.moves_correct,
+                 completions = completions + excluded.completions,
+                 last_drilled = excluded.last_drilled",
+            params![line_id, correct as i32, finished as i32, now],
+        )
+        .map_err(|e| format!("Failed to record drill move: {}", e))?;
+        Ok(())
+    }
+
+    /// Drill results of every line drilled so far, by line id
+    pub fn get_drill_stats(&self) -> Result<HashMap<String, DrillStats>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let mut stmt = conn
+            .prepare("SELECT line_id, moves_played, moves_correct, completions, last_drilled FROM drill_stats")
+            .map_err(|e| format!("Query error: {}", e))?;
+
+        let stats = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    DrillStats {
+                        moves_played: row.get(1)?,
+                        moves_correct: row.get(2)?,
+                        completions: row.get(3)?,
+                        last_drilled: row.get(4)?,
+                    },
+                ))
+            })
+            .map_err(|e| format!("Query map error: {}", e))?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(stats)
+    }
+
+    /// Record where an analyzed game left the `color` repertoire, with its
+    /// position key and the moves before it, or that it did not
+    pub fn save_repertoire_deviation(
+        &self,
+        game_url: &str,
+        color: &str,
+        date: &str,
+        deviation: Option<(u64, &RepertoireDeviation, &str)>,
+    ) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        match deviation {
+            Some((key, d, line)) => conn.execute(
+                "INSERT OR REPLACE INTO repertoire_deviations (game_url, color, position_key, fen, line, played, user_error, date)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    game_url,
+                    color,
+                    key as i64,
+                    d.fen,
+                    line,
+                    d.played,
+                    d.kind == DeviationKind::UserError,
+                    date
+                ],
+            ),
+            None => conn.execute("DELETE FROM repertoire_deviations WHERE game_url = ?1", params![game_url]),
+        }
+        .map_err(|e| format!("Failed to save repertoire deviation: {}", e))?;
+        Ok(())
+    }
+
+    /// Positions where the user left the repertoire in the most games, with
+    /// the latest game's line and move. `expected` is filled in by the caller.
+    pub fn get_forgotten_lines(&self, limit: usize) -> Result<Vec<(u64, ForgottenLine)>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        // SQLite takes the bare columns from the row holding MAX(date).
+        // Positions since removed from the repertoire are left out.
+        let mut stmt = conn
+            .prepare(
+                "SELECT color, position_key, fen, line, played, game_url, MAX(date), COUNT(*) AS times
+                 FROM repertoire_deviations d
+                 WHERE user_error = 1 AND EXISTS (
+                     SELECT 1 FROM repertoire_moves r WHERE r.color = d.color AND r.position_key = d.position_key
+                 )
+                 GROUP BY color, position_key
+                 ORDER BY times DESC, MAX(date) DESC LIMIT ?1",
+            )
+            .map_err(|e| format!("Query error: {}", e))?;
+
+        let lines = stmt
+            .query_map(params![limit as i64], |row| {
+                let line: String = row.get(3)?;
+                Ok((
+                    row.get::<_, i64>(1)? as u64,
+                    ForgottenLine {
+                        color: row.get(0)?,
+                        fen: row.get(2)?,
+                        line: line.split_whitespace().map(|s| s.to_string()).collect(),
+                        expected: Vec::new(),
+                        played: row.get(4)?,
+                        times: row.get(7)?,
+                        last_game_url: row.get(5)?,
+                        last_date: row.get(6)?,
+                    },
+                ))
+            })
+            .map_err(|e| format!("Query map error: {}", e))?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(lines)
+    }
+
+    /// Set or, with an empty note, clear the comment on a repertoire position
+    pub fn set_repertoire_note(&self, color: &str, key: u64, fen: &str, note: &str) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        if note.trim().is_empty() {
+            conn.execute(
+                "DELETE FROM repertoire_notes WHERE color = ?1 AND position_key = ?2",
+                params![color, key as i64],
+            )
+        } else {
+            conn.execute(
+                "INSERT OR REPLACE INTO repertoire_notes (color, position_key, fen, note) VALUES (?1, ?2, ?3, ?4)",
+                params![color, key as i64, fen, note.trim()],
+            )
+        }
+        .map_err(|e| format!("Failed to save repertoire note: {}", e))?;
+        Ok(())
+    }
+
+    pub fn get_repertoire_notes(&self, color: &str) -> Result<HashMap<u64, String>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let mut stmt = conn
+            .prepare("SELECT position_key, note FROM repertoire_notes WHERE color = ?1")
+            .map_err(|e| format!("Query error: {}", e))?;
+
+        let notes = stmt
+            .query_map(params![color], |row| Ok((row.get::<_, i64>(0)? as u64, row.get(1)?)))
+            .map_err(|e| format!("Query map error: {}", e))?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(notes)
+    }
+}
+
+fn puzzle_from_row(row: &rusqlite::Row) -> rusqlite::Result<Puzzle> {
+    let json = |idx: usize| -> rusqlite::Result<String> { row.get(idx) };
+    Ok(Puzzle {
+        id: row.get(0)?,
+        game_url: row.get(1)?,
+        move_index: row.get::<_, i64>(2)? as usize,
+        fen: row.get(3)?,
+        color: row.get(4)?,
+        played_move: row.get(5)?,
+        solution: serde_json::from_str(&json(6)?).unwrap_or_default(),
+        solution_san: serde_json::from_str(&json(7)?).unwrap_or_default(),
+        themes: serde_json::from_str(&json(8)?).unwrap_or_default(),
+        mate_in: row.get(9)?,
+        rating: row.get(10)?,
+        attempts: row.get(11)?,
+        solved: row.get(12)?,
+    })
+}
+
+fn game_from_row(row: &rusqlite::Row) -> rusqlite::Result<ChessComGame> {
+    use crate::models::ChessComPlayer;
+
+    Ok(ChessComGame {
+        url: row.get(0)?,
+        pgn: row.get(1)?,
+        time_control: row.get(2)?,
+        end_time: row.get::<_, Option<i64>>(3)?.map(|v| v as u64),
+        rated: row.get::<_, Option<i32>>(4)?.map(|v| v != 0),
+        time_class: row.get(5)?,
+        rules: row.get(6)?,
+        white: ChessComPlayer {
+            username: row.get(7)?,
+            rating: row.get::<_, Option<i32>>(8)?.map(|v| v as u32),
+            result: row.get(9)?,
+            id: None,
+        },
+        black: ChessComPlayer {
+            username: row.get(10)?,
+            rating: row.get::<_, Option<i32>>(11)?.map(|v| v as u32),
+            result: row.get(12)?,
+            id: None,
+        },
+    })
+}
+
+fn review_item_from_row(row: &rusqlite::Row) -> rusqlite::Result<ReviewItem> {
+    let kind: String = row.get(1)?;
+    let solution: String = row.get(5)?;
+    Ok(ReviewItem {
+        id: row.get(0)?,
+        kind: ReviewKind::from_code(&kind).unwrap_or(ReviewKind::Mistake),
+        source_id: row.get(2)?,
+        title: row.get(3)?,
+        fen: row.get(4)?,
+        solution: serde_json::from_str(&solution).unwrap_or_default(),
+        ease: row.get(6)?,
+        interval_days: row.get(7)?,
+        repetitions: row.get(8)?,
+        lapses: row.get(9)?,
+        due_at: row.get(10)?,
+    })
+}
+
+/// Move comments from the old per-game `coach_comments` table into
+/// `coach_cache`, keyed by the cached analysis of the move they were written
+/// for. They keep the legacy prompt in their key, so they stay linked to their
+/// game but never answer a lookup for the current prompt. Rows whose game has
+/// no cached analysis stay behind; the table is dropped once it is empty.
+fn migrate_coach_comments(conn: &Connection) -> Result<(), String> {
+    let exists: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'coach_comments'",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Query error: {}", e))?;
+    if exists == 0 {
+        return Ok(());
+    }
+
+    let model: String = conn
+        .query_row("SELECT value FROM settings WHERE key = 'coach_model'", [], |row| row.get(0))
+        .unwrap_or_default();
+    let rows: Vec<(i64, String, i64, String, String)> = {
+        let mut stmt = conn
+            .prepare(
+                "SELECT c.id, c.game_url, c.move_index, c.comment, a.analysis_json
+                 FROM coach_comments c JOIN analysis_cache a ON a.game_url = c.game_url",
+            )
+            .map_err(|e| format!("Query error: {}", e))?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)))
+            .map_err(|e| format!("Query map error: {}", e))?
+            .filter_map(|r| r.ok())
+            .collect();
+        rows
+    };
+
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|e| format!("Failed to migrate coach comments: {}", e))?;
+    for (id, game_url, move_index, comment, analysis_json) in rows {
+        let analysis: Option<GameAnalysis> = serde_json::from_str(&analysis_json).ok();
+        let Some(m) = analysis.as_ref().and_then(|a| a.moves.get(move_index as usize)) else {
+            continue;
+        };
+        let key = CommentKey {
+            fen: m.fen_before.clone(),
+            played_move: m.san.clone(),
+            best_move: m.best_move_san.clone(),
+            classification: format!("{:?}", m.classification),
+            model: model.clone(),
+            style: CoachStyle::default(),
+            prompt: coach::Prompt::Legacy,
+        };
+        let cache_key = key.hash();
+        tx.execute(
+            "INSERT OR IGNORE INTO coach_cache (cache_key, fen, played_move, classification, provider, model, prompt_version, comment)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                cache_key,
+                key.fen,
+                key.played_move,
+                key.classification,
+                coach::PROVIDER,
+                key.model,
+                key.prompt.version(),
+                comment,
+            ],
+        )
+        .map_err(|e| format!("Failed to migrate coach comments: {}", e))?;
+        tx.execute(
+            "INSERT OR IGNORE INTO coach_comment_refs (game_url, move_index, cache_key) VALUES (?1, ?2, ?3)",
+            params![game_url, move_index, cache_key],
+        )
+        .map_err(|e| format!("Failed to migrate coach comments: {}", e))?;
+        tx.execute("DELETE FROM coach_comments WHERE id = ?1", params![id])
+            .map_err(|e| format!("Failed to migrate coach comments: {}", e))?;
+    }
+
+    let left: i64 = tx
+        .query_row("SELECT COUNT(*) FROM coach_comments", [], |row| row.get(0))
+        .map_err(|e| format!("Query error: {}", e))?;
+    if left == 0 {
+        tx.execute_batch("DROP TABLE coach_comments;")
+            .map_err(|e| format!("Failed to migrate coach comments: {}", e))?;
+    }
+    tx.commit().map_err(|e| format!("Failed to migrate coach comments: {}", e))
 }
@@ -0,0 +1,235 @@
+use crate::analysis;
+use crate::eco;
+use crate::models::{DrillLine, DrillSource, DrillStats, DrillStep, Opening};
+use crate::polyglot;
+use crate::repertoire::{self, Edge};
+use shakmaty::{uci::UciMove, Chess, Position};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// The lines of a drill merged by position, so lines that transpose into
+/// each other share their moves
+struct Graph {
+    edges: HashMap<u64, Vec<Edge>>,
+}
+
+impl Graph {
+    fn new(lines: &[DrillLine]) -> Result<Graph, String> {
+        let mut edges: HashMap<u64, Vec<Edge>> = HashMap::new();
+        for line in lines {
+            for e in repertoire::line_edges(&line.moves)? {
+                let from = edges.entry(e.key).or_default();
+                if !from.iter().any(|f| f.next_key == e.next_key) {
+                    from.push(e);
+                }
+            }
+        }
+        Ok(Graph { edges })
+    }
+
+    fn moves(&self, key: u64) -> &[Edge] {
+        self.edges.get(&key).map(|e| e.as_slice()).unwrap_or_default()
+    }
+
+    /// Whether `target` can still be reached from `key` along the lines
+    fn reaches(&self, key: u64, target: u64) -> bool {
+        let mut seen = HashSet::from([key]);
+        let mut queue = VecDeque::from([key]);
+        while let Some(k) = queue.pop_front() {
+            if k == target {
+                return true;
+            }
+            for e in self.moves(k) {
+                if seen.insert(e.next_key) {
+                    queue.push_back(e.next_key);
+                }
+            }
+        }
+        false
+    }
+
+    /// Moves from `key` that keep the drilled line within reach
+    fn toward(&self, key: u64, target: u64) -> Vec<&Edge> {
+        self.moves(key).iter().filter(|e| self.reaches(e.next_key, target)).collect()
+    }
+}
+
+/// One line for each end of the repertoire tree, reached by the first move
+/// order stored; transposed orders are covered by the drill graph.
+pub fn repertoire_lines(color: &str, edges: &[Edge]) -> Vec<DrillLine> {
+    let mut tree: HashMap<u64, Vec<&Edge>> = HashMap::new();
+    for e in edges {
+        tree.entry(e.key).or_default().push(e);
+    }
+
+    let root = polyglot::key(&Chess::default());
+    let mut lines = Vec::new();
+    let mut ends = HashSet::new();
+    let mut path = vec![root];
+    let mut moves = Vec::new();
+    collect_lines(&tree, root, &mut path, &mut moves, &mut |moves, key| {
+        if !ends.insert(key) {
+            return;
+        }
+        lines.push(DrillLine {
+            id: format!("repertoire:{}:{:016x}", color, key),
+            source: DrillSource::Repertoire,
+            name: line_name(moves).unwrap_or_else(|| format!("{} repertoire", side_name(color))),
+            color: color.to_string(),
+            moves: moves.to_vec(),
+            stats: DrillStats::default(),
+        });
+    });
+    lines
+}
+
+fn collect_lines(
+    tree: &HashMap<u64, Vec<&Edge>>,
+    key: u64,
+    path: &mut Vec<u64>,
+    moves: &mut Vec<String>,
+    emit: &mut dyn FnMut(&[String], u64),
+) {
+    let children: Vec<&Edge> = tree
+        .get(&key)
+        .map(|c| c.iter().filter(|e| !path.contains(&e.next_key)).copied().collect())
+        .unwrap_or_default();
+    if children.is_empty() {
+        if !moves.is_empty() {
+            emit(moves, key);
+        }
+        return;
+    }
+
+    for e in children {
+        path.push(e.next_key);
+        moves.push(e.san.clone());
+        collect_lines(tree, e.next_key, path, moves, emit);
+        moves.pop();
+        path.pop();
+    }
+}
+
+/// Lines of the opening catalog. Each belongs to the side that plays its
+/// last move, the move the opening is named after.
+pub fn catalog_lines(openings: &[Opening]) -> Vec<DrillLine> {
+    openings
+        .iter()
+        .filter(|o| !o.moves.is_empty())
+        .map(|o| DrillLine {
+            id: format!("catalog:{}", o.id),
+            source: DrillSource::Catalog,
+            name: o.name.clone(),
+            color: if o.moves.len() % 2 == 1 { "white" } else { "black" }.to_string(),
+            moves: o.moves.clone(),
+            stats: DrillStats::default(),
+        })
+        .collect()
+}
+
+/// Name of the deepest catalogued position of a line
+fn line_name(moves: &[String]) -> Option<String> {
+    eco::classify(moves).opening.map(|o| o.name.clone())
+}
+
+fn side_name(color: &str) -> &str {
+    if color == "white" {
+        "White"
+    } else {
+        "Black"
+    }
+}
+
+/// Begin a drill of `target`; the app moves first when the user has Black.
+/// `lines` are the drill's other lines, which the user may transpose through.
+pub fn start(target: &DrillLine, lines: &[DrillLine]) -> Result<DrillStep, String> {
+    let graph = Graph::new(lines)?;
+    let end = end_key(target)?;
+    let pos = Chess::default();
+    if repertoire::user_to_move(&target.color, &pos) {
+        return Ok(DrillStep {
+            correct: true,
+            finished: false,
+            fen: analysis::format_fen(&pos),
+            reply: None,
+            reply_san: None,
+            expected: Vec::new(),
+            expected_uci: Vec::new(),
+        });
+    }
+    Ok(answer(&graph, target, end, pos))
+}
+
+/// Check the user's move in `fen`. Any prepared move that still leads to the
+/// end of the target line is right, whatever the move order.
+pub fn check_move(target: &DrillLine, lines: &[DrillLine], fen: &str, uci: &str) -> Result<DrillStep, String> {
+    let graph = Graph::new(lines)?;
+    let end = end_key(target)?;
+    let pos = analysis::parse_fen(fen)?;
+    if !repertoire::user_to_move(&target.color, &pos) {
+        return Err("It is not the user's move".to_string());
+    }
+
+    let m = uci
+        .parse::<UciMove>()
+        .map_err(|e| format!("Invalid move {}: {}", uci, e))?
+        .to_move(&pos)
+        .map_err(|e| format!("Illegal move {}: {}", uci, e))?;
+    let mut after = pos.clone();
+    after.play_unchecked(&m);
+    let after_key = polyglot::key(&after);
+
+    let allowed = graph.toward(polyglot::key(&pos), end);
+    if !allowed.iter().any(|e| e.next_key == after_key) {
+        return Ok(DrillStep {
+            correct: false,
+            finished: false,
+            fen: analysis::format_fen(&pos),
+            reply: None,
+            reply_san: None,
+            expected: allowed.iter().map(|e| e.san.clone()).collect(),
+            expected_uci: allowed.iter().map(|e| e.uci.clone()).collect(),
+        });
+    }
+    Ok(answer(&graph, target, end, after))
+}
+
+/// The app's reply in `pos`, preferring the target line's own move
+fn answer(graph: &Graph, target: &DrillLine, end: u64, pos: Chess) -> DrillStep {
+    let key = polyglot::key(&pos);
+    let replies = graph.toward(key, end);
+    let on_line: HashSet<u64> = repertoire::line_edges(&target.moves)
+        .map(|edges| edges.iter().map(|e| e.next_key).collect())
+        .unwrap_or_default();
+    let reply = replies
+        .iter()
+        .find(|e| on_line.contains(&e.next_key))
+        .or_else(|| replies.first());
+
+    let Some(reply) = reply.filter(|_| key != end) else {
+        return DrillStep {
+            correct: true,
+            finished: true,
+            fen: analysis::format_fen(&pos),
+            reply: None,
+            reply_san: None,
+            expected: Vec::new(),
+            expected_uci: Vec::new(),
+        };
+    };
+    DrillStep {
+        correct: true,
+        finished: reply.next_key == end,
+        fen: reply.fen_after.clone(),
+        reply: Some(reply.uci.clone()),
+        reply_san: Some(reply.san.clone()),
+        expected: Vec::new(),
+        expected_uci: Vec::new(),
+    }
+}
+
+fn end_key(line: &DrillLine) -> Result<u64, String> {
+    repertoire::line_edges(&line.moves)?
+        .last()
+        .map(|e| e.next_key)
+        .ok_or_else(|| format!("Line {} has no moves", line.id))
+}
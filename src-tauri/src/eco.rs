@@ -0,0 +1,123 @@
+use crate::analysis;
+use crate::models::EcoOpening;
+use shakmaty::{san::SanPlus, Chess, Position};
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+/// Bundled ECO table (A00-E99 with named variations): `eco<TAB>name<TAB>pgn`
+const ECO_TSV: &str = include_str!("../resources/eco.tsv");
+
+struct EcoIndex {
+    openings: Vec<EcoOpening>,
+    /// Position (EPD) at the end of each line, to the line naming it
+    named: HashMap<String, usize>,
+    /// Every position reached along any line
+    theory: HashSet<String>,
+}
+
+/// Where a game left the ECO table and which line it reached
+pub struct OpeningMatch {
+    /// Leading plies whose positions are all in the table
+    pub book_plies: usize,
+    /// Deepest named position of the game, found by position so transpositions count
+    pub opening: Option<&'static EcoOpening>,
+}
+
+fn index() -> &'static EcoIndex {
+    static INDEX: OnceLock<EcoIndex> = OnceLock::new();
+    INDEX.get_or_init(load)
+}
+
+fn load() -> EcoIndex {
+    let mut index = EcoIndex {
+        openings: Vec::new(),
+        named: HashMap::new(),
+        theory: HashSet::new(),
+    };
+
+    for (n, line) in ECO_TSV.lines().enumerate().skip(1) {
+        let mut fields = line.split('\t');
+        let (Some(eco), Some(name), Some(pgn)) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        let moves = pgn_moves(pgn);
+
+        let mut pos = Chess::default();
+        let mut epds = Vec::with_capacity(moves.len());
+        for san in &moves {
+            match SanPlus::from_ascii(san.as_bytes()).ok().and_then(|s| s.san.to_move(&pos).ok()) {
+                Some(m) => pos.play_unchecked(&m),
+                None => break,
+            }
+            epds.push(analysis::format_fen(&pos));
+        }
+        if epds.len() != moves.len() || moves.is_empty() {
+            eprintln!("eco.tsv line {}: illegal line {} {}", n + 1, eco, pgn);
+            continue;
+        }
+
+        let id = index.openings.len();
+        index.named.entry(epds[epds.len() - 1].clone()).or_insert(id);
+        index.theory.extend(epds);
+        index.openings.push(EcoOpening {
+            eco: eco.to_string(),
+            name: name.to_string(),
+            pgn: pgn.to_string(),
+            moves,
+        });
+    }
+
+    index
+}
+
+/// SAN moves of a PGN move list, without move numbers
+fn pgn_moves(pgn: &str) -> Vec<String> {
+    pgn.split_whitespace()
+        .filter(|t| !t.ends_with('.'))
+        .map(|t| t.to_string())
+        .collect()
+}
+
+/// Every line of the table, in ECO order
+pub fn all() -> &'static [EcoOpening] {
+    &index().openings
+}
+
+/// The line whose final position is `pos`, whatever the move order
+pub fn lookup(pos: &Chess) -> Option<&'static EcoOpening> {
+    let index = index();
+    index
+        .named
+        .get(&analysis::format_fen(pos))
+        .map(|&i| &index.openings[i])
+}
+
+/// Whether `pos` appears anywhere in the table
+pub fn is_theory(pos: &Chess) -> bool {
+    index().theory.contains(&analysis::format_fen(pos))
+}
+
+/// Classify a game from its SAN moves. The book ends at the first move that
+/// leaves the table; the opening is still named by the deepest catalogued
+/// position, which may be reached later by transposition.
+pub fn classify(san_moves: &[String]) -> OpeningMatch {
+    let mut pos = Chess::default();
+    let mut book_plies = 0;
+    let mut opening = None;
+
+    for (ply, san) in san_moves.iter().enumerate() {
+        let Some(m) = SanPlus::from_ascii(san.as_bytes()).ok().and_then(|s| s.san.to_move(&pos).ok()) else {
+            break;
+        };
+        pos.play_unchecked(&m);
+
+        if book_plies == ply && is_theory(&pos) {
+            book_plies = ply + 1;
+        }
+        if let Some(found) = lookup(&pos) {
+            opening = Some(found);
+        }
+    }
+
+    OpeningMatch { book_plies, opening }
+}
@@ -0,0 +1,526 @@
+use crate::see;
+use crate::tactics::piece_value;
+use shakmaty::zobrist::{Zobrist64, ZobristHash};
+use shakmaty::{Chess, Color, EnPassantMode, Move, Position, Role, Square};
+
+/// Score of delivering mate; a mate in `n` plies scores `MATE - n`
+pub const MATE: i32 = 30_000;
+const INFINITY: i32 = MATE + 1;
+const MAX_PLY: u32 = 64;
+const TABLE_SIZE: usize = 1 << 15;
+
+/// How far a search may go
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub depth: u8,
+    pub nodes: u64,
+}
+
+impl Limits {
+    /// Cheap enough to run on every ply of a game
+    pub const QUICK: Limits = Limits { depth: 4, nodes: 150_000 };
+    /// For single positions where the answer has to hold up, e.g. puzzles
+    pub const DEEP: Limits = Limits { depth: 6, nodes: 1_500_000 };
+}
+
+/// A principal variation, scored in centipawns for the side to move
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub moves: Vec<Move>,
+    pub score: i32,
+}
+
+impl Line {
+    pub fn best(&self) -> &Move {
+        &self.moves[0]
+    }
+}
+
+/// Moves to mate for a score: positive if the side to move mates, negative
+/// if it gets mated
+pub fn mate_in(score: i32) -> Option<i32> {
+    if score.abs() < MATE - MAX_PLY as i32 {
+        return None;
+    }
+    let moves = (MATE - score.abs() + 1) / 2;
+    Some(if score > 0 { moves } else { -moves })
+}
+
+/// Search a position and return its `multipv` best lines, best first.
+/// Scores are exact for the returned lines; other moves are only known to be
+/// worse. Returns an empty list when the game is over.
+pub fn analyse(pos: &Chess, limits: Limits, multipv: usize) -> Vec<Line> {
+    let mut search = Search::new(limits.nodes);
+    let mut root: Vec<Move> = pos.legal_moves().into_iter().collect();
+    root.sort_by_key(|m| -order_score(m));
+
+    let multipv = multipv.max(1);
+    let mut lines: Vec<Line> = Vec::new();
+
+    for depth in 1..=limits.depth.max(1) as i32 {
+        let mut scored: Vec<Line> = Vec::with_capacity(root.len());
+        for m in &root {
+            // Only the current top `multipv` need exact scores
+            let floor = if scored.len() >= multipv {
+                let mut scores: Vec<i32> = scored.iter().map(|l| l.score).collect();
+                scores.sort_unstable_by(|a, b| b.cmp(a));
+                scores[multipv - 1]
+            } else {
+                -INFINITY
+            };
+
+            let mut child = pos.clone();
+            child.play_unchecked(m);
+            let mut pv = Vec::new();
+            let score = -search.negamax(&child, depth - 1, 1, -INFINITY, -floor, &mut pv);
+            if search.stopped && depth > 1 {
+                break;
+            }
+            pv.insert(0, m.clone());
+            scored.push(Line { moves: pv, score });
+        }
+        if search.stopped && depth > 1 {
+            break;
+        }
+
+        scored.sort_by_key(|l| std::cmp::Reverse(l.score));
+        root = scored.iter().map(|l| l.best().clone()).collect();
+        scored.truncate(multipv);
+        lines = scored;
+
+        // A forced mate found within the horizon will not change
+        if lines.first().and_then(|l| mate_in(l.score)).is_some_and(|n| n > 0 && n * 2 - 1 <= depth) {
+            break;
+        }
+    }
+
+    lines
+}
+
+/// Shortest forced mate for the side to move within `max_moves`, as the
+/// mating line. With more than two moves left only checks are tried for
+/// the attacker, which keeps the search small enough to run on every ply.
+pub fn find_mate(pos: &Chess, max_moves: u32) -> Option<Vec<Move>> {
+    let mut nodes = MATE_SEARCH_NODES;
+    (1..=max_moves).find_map(|n| mate_line(pos, n, &mut nodes))
+}
+
+/// Whether the side to move is mated within `moves` whatever it plays
+pub fn is_mated_within(pos: &Chess, moves: u32) -> bool {
+    let mut nodes = MATE_SEARCH_NODES;
+    pos.is_checkmate() || (moves > 0 && every_reply_mated(pos, moves, &mut nodes).is_some())
+}
+
+const MATE_SEARCH_NODES: u64 = 200_000;
+
+fn mate_line(pos: &Chess, moves: u32, nodes: &mut u64) -> Option<Vec<Move>> {
+    for m in pos.legal_moves() {
+        if *nodes == 0 {
+            return None;
+        }
+        *nodes -= 1;
+
+        let mut child = pos.clone();
+        child.play_unchecked(&m);
+        let gives_check = child.is_check();
+        if child.is_checkmate() {
+            return Some(vec![m]);
+        }
+        if moves == 1 || (moves > 2 && !gives_check) {
+            continue;
+        }
+        if let Some(rest) = every_reply_mated(&child, moves - 1, nodes) {
+            let mut line = vec![m];
+            line.extend(rest);
+            return Some(line);
+        }
+    }
+    None
+}
+
+/// The defence that lasts longest when every reply gets mated in `moves`
+fn every_reply_mated(pos: &Chess, moves: u32, nodes: &mut u64) -> Option<Vec<Move>> {
+    let replies = pos.legal_moves();
+    if replies.is_empty() {
+        return None; // stalemate (mate is handled by the caller)
+    }
+
+    let mut longest: Option<Vec<Move>> = None;
+    for reply in replies {
+        let mut child = pos.clone();
+        child.play_unchecked(&reply);
+        let line = mate_line(&child, moves, nodes)?;
+        if longest.as_ref().is_none_or(|l| line.len() + 1 > l.len()) {
+            let mut full = vec![reply];
+            full.extend(line);
+            longest = Some(full);
+        }
+    }
+    longest
+}
+
+/// Static evaluation in centipawns for the side to move
+pub fn evaluate(pos: &Chess) -> i32 {
+    let board = pos.board();
+    let phase = game_phase(pos);
+    let mut score = 0;
+
+    for (sq, piece) in board.clone() {
+        let value = piece_value(piece.role) + square_bonus(piece.role, piece.color, sq, phase);
+        score += if piece.color == Color::White { value } else { -value };
+    }
+    for color in [Color::White, Color::Black] {
+        if (board.bishops() & board.by_color(color)).count() >= 2 {
+            score += if color == Color::White { 30 } else { -30 };
+        }
+    }
+
+    if pos.turn() == Color::White {
+        score
+    } else {
+        -score
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Clone)]
+struct Entry {
+    key: u64,
+    depth: i32,
+    score: i32,
+    bound: Bound,
+    best: Option<Move>,
+}
+
+struct Search {
+    nodes: u64,
+    limit: u64,
+    stopped: bool,
+    table: Vec<Option<Entry>>,
+    killers: Vec<[Option<Move>; 2]>,
+}
+
+impl Search {
+    fn new(limit: u64) -> Self {
+        Self {
+            nodes: 0,
+            limit,
+            stopped: false,
+            table: vec![None; TABLE_SIZE],
+            killers: vec![[None, None]; MAX_PLY as usize + 1],
+        }
+    }
+
+    fn tick(&mut self) -> bool {
+        self.nodes += 1;
+        if self.nodes >= self.limit {
+            self.stopped = true;
+        }
+        self.stopped
+    }
+
+    fn negamax(&mut self, pos: &Chess, depth: i32, ply: u32, mut alpha: i32, beta: i32, pv: &mut Vec<Move>) -> i32 {
+        pv.clear();
+        if self.tick() {
+            return 0;
+        }
+
+        let moves = pos.legal_moves();
+        let in_check = pos.is_check();
+        if moves.is_empty() {
+            return if in_check { -(MATE - ply as i32) } else { 0 };
+        }
+        if pos.is_insufficient_material() || pos.halfmoves() >= 100 {
+            return 0;
+        }
+        if ply >= MAX_PLY {
+            return evaluate(pos);
+        }
+
+        // Never stop the search while in check
+        let depth = if in_check { depth + 1 } else { depth };
+        if depth <= 0 {
+            return self.quiesce(pos, ply, alpha, beta);
+        }
+
+        let key = pos.zobrist_hash::<Zobrist64>(EnPassantMode::Legal).0;
+        let slot = key as usize % TABLE_SIZE;
+        let mut hash_move = None;
+        if let Some(entry) = self.table[slot].as_ref().filter(|e| e.key == key) {
+            hash_move = entry.best.clone();
+            if entry.depth >= depth {
+                let score = score_from_table(entry.score, ply);
+                match entry.bound {
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+        }
+
+        let killers = self.killers[ply as usize].clone();
+        let mut ordered: Vec<Move> = moves.into_iter().collect();
+        ordered.sort_by_key(|m| {
+            if Some(m) == hash_move.as_ref() {
+                i32::MIN
+            } else if killers.contains(&Some(m.clone())) {
+                -5_000
+            } else {
+                -order_score(m)
+            }
+        });
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut child_pv = Vec::new();
+
+        for m in ordered {
+            let mut child = pos.clone();
+            child.play_unchecked(&m);
+            let score = -self.negamax(&child, depth - 1, ply + 1, -beta, -alpha, &mut child_pv);
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(m.clone());
+            }
+            if score > alpha {
+                alpha = score;
+                pv.clear();
+                pv.push(m.clone());
+                pv.extend(child_pv.iter().cloned());
+            }
+            if alpha >= beta {
+                if !m.is_capture() {
+                    let slot = &mut self.killers[ply as usize];
+                    if slot[0].as_ref() != Some(&m) {
+                        slot[1] = slot[0].take();
+                        slot[0] = Some(m);
+                    }
+                }
+                break;
+            }
+        }
+
+        let bound = if best_score <= original_alpha {
+            Bound::Upper
+        } else if best_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table[slot] = Some(Entry {
+            key,
+            depth,
+            score: score_to_table(best_score, ply),
+            bound,
+            best: best_move,
+        });
+
+        best_score
+    }
+
+    /// Resolve captures (and check evasions) so the static eval is not taken
+    /// in the middle of an exchange
+    fn quiesce(&mut self, pos: &Chess, ply: u32, mut alpha: i32, beta: i32) -> i32 {
+        if self.tick() {
+            return 0;
+        }
+
+        let in_check = pos.is_check();
+        let moves = pos.legal_moves();
+        if moves.is_empty() {
+            return if in_check { -(MATE - ply as i32) } else { 0 };
+        }
+        if ply >= MAX_PLY {
+            return evaluate(pos);
+        }
+
+        let mut best_score = -INFINITY;
+        if !in_check {
+            best_score = evaluate(pos);
+            if best_score >= beta {
+                return best_score;
+            }
+            alpha = alpha.max(best_score);
+        }
+
+        let mut ordered: Vec<Move> = moves
+            .into_iter()
+            .filter(|m| in_check || ((m.is_capture() || m.is_promotion()) && see::see(pos, m) >= 0))
+            .collect();
+        ordered.sort_by_key(|m| -order_score(m));
+
+        for m in ordered {
+            let mut child = pos.clone();
+            child.play_unchecked(&m);
+            let score = -self.quiesce(&child, ply + 1, -beta, -alpha);
+            if self.stopped {
+                return 0;
+            }
+            best_score = best_score.max(score);
+            if score > alpha {
+                alpha = score;
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+
+        best_score
+    }
+}
+
+/// Mate scores are stored relative to the node so they stay valid when the
+/// same position is reached at another ply
+fn score_to_table(score: i32, ply: u32) -> i32 {
+    if score >= MATE - MAX_PLY as i32 {
+        score + ply as i32
+    } else if score <= -(MATE - MAX_PLY as i32) {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_table(score: i32, ply: u32) -> i32 {
+    if score >= MATE - MAX_PLY as i32 {
+        score - ply as i32
+    } else if score <= -(MATE - MAX_PLY as i32) {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+/// Most valuable victim, least valuable attacker; promotions next
+fn order_score(m: &Move) -> i32 {
+    let mut score = 0;
+    if let Some(victim) = m.capture() {
+        score += 10_000 + piece_value(victim) * 10 - m.role() as i32;
+    }
+    if let Some(promotion) = m.promotion() {
+        score += 8_000 + piece_value(promotion);
+    }
+    score
+}
+
+/// 24 with all pieces on the board, 0 in a pawn ending
+fn game_phase(pos: &Chess) -> i32 {
+    let board = pos.board();
+    let phase = (board.knights() | board.bishops()).count() as i32
+        + board.rooks().count() as i32 * 2
+        + board.queens().count() as i32 * 4;
+    phase.min(24)
+}
+
+/// Piece-square bonus; tables are written from White's side, rank 8 first
+fn square_bonus(role: Role, color: Color, sq: Square, phase: i32) -> i32 {
+    let rank = if color == Color::White {
+        7 - sq.rank() as usize
+    } else {
+        sq.rank() as usize
+    };
+    let idx = rank * 8 + sq.file() as usize;
+    match role {
+        Role::Pawn => PAWN[idx],
+        Role::Knight => KNIGHT[idx],
+        Role::Bishop => BISHOP[idx],
+        Role::Rook => ROOK[idx],
+        Role::Queen => QUEEN[idx],
+        Role::King => (KING_MIDDLE[idx] * phase + KING_END[idx] * (24 - phase)) / 24,
+    }
+}
+
+#[rustfmt::skip]
+const PAWN: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const KING_MIDDLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+#[rustfmt::skip]
+const KING_END: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
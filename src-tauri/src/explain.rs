@@ -0,0 +1,353 @@
+use crate::engine;
+use crate::i18n::{self, Text};
+use crate::models::{Language, MoveClassification, ThreatKind};
+use crate::see;
+use crate::tactics;
+use crate::threats;
+use shakmaty::{san::SanPlus, Bitboard, Chess, Color, Move, Piece, Position, Role, Square};
+
+/// Material swing (centipawns) before a capture sequence is worth explaining
+const MATERIAL_THRESHOLD: i32 = 150;
+
+/// A concrete fact about a move, detected from the position alone
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fact {
+    /// The move checkmates
+    DeliversMate,
+    /// A mate in one was available but not played
+    MissesMate { mate: String },
+    /// The opponent's mate or capture threat still works after the move
+    IgnoresThreat { threat: String },
+    /// The moved side loses material to the given reply
+    DropsPiece { role: Role, reply: String },
+    /// The opponent gets a fork that was not available before
+    AllowsFork { square: Square },
+    /// The move wins material outright
+    WinsPiece { role: Role },
+    /// The move trades off the bishop that is not blocked by its own pawns
+    TradesGoodBishop,
+}
+
+impl Fact {
+    fn text(&self) -> Text {
+        match self {
+            Fact::DeliversMate => Text::DeliversMate,
+            Fact::MissesMate { .. } => Text::MissesMate,
+            Fact::IgnoresThreat { .. } => Text::IgnoresThreat,
+            Fact::DropsPiece { .. } => Text::DropsPiece,
+            Fact::AllowsFork { .. } => Text::AllowsFork,
+            Fact::WinsPiece { .. } => Text::WinsPiece,
+            Fact::TradesGoodBishop => Text::TradesGoodBishop,
+        }
+    }
+
+    /// Whether the fact counts against the move
+    fn is_negative(&self) -> bool {
+        !matches!(self, Fact::DeliversMate | Fact::WinsPiece { .. })
+    }
+
+    /// Whether the fact fits a move labelled `classification`: only an
+    /// inaccuracy, mistake or blunder is blamed, and none of them is praised
+    fn fits(&self, classification: &MoveClassification) -> bool {
+        let bad = matches!(
+            classification,
+            MoveClassification::Inaccuracy | MoveClassification::Mistake | MoveClassification::Blunder
+        );
+        self.is_negative() == bad
+    }
+
+    /// Placeholder values for this fact (besides `{move}`, which is always set)
+    fn args(&self, language: Language) -> Vec<(&'static str, String)> {
+        match self {
+            Fact::DeliversMate | Fact::TradesGoodBishop => vec![],
+            Fact::MissesMate { mate } => vec![("mate", mate.clone())],
+            Fact::IgnoresThreat { threat } => vec![("threat", threat.clone())],
+            Fact::DropsPiece { role, reply } => vec![
+                ("piece", i18n::piece_name(language, *role).to_string()),
+                ("reply", reply.clone()),
+            ],
+            Fact::AllowsFork { square } => vec![("square", square.to_string())],
+            Fact::WinsPiece { role } => vec![("piece", i18n::piece_name(language, *role).to_string())],
+        }
+    }
+}
+
+/// Explain a move in one sentence from the most important detected fact
+/// that agrees with its classification. `last_capture` is the square and
+/// piece the opponent's previous move took.
+pub fn explain_move(
+    pos: &Chess,
+    m: &Move,
+    last_capture: Option<(Square, Role)>,
+    classification: &MoveClassification,
+    language: Language,
+) -> Option<String> {
+    let fact = detect_facts(pos, m, last_capture)
+        .into_iter()
+        .find(|f| f.fits(classification))?;
+    let mut args = vec![("move", SanPlus::from_move(pos.clone(), m).to_string())];
+    args.extend(fact.args(language));
+    Some(i18n::render(i18n::text(language, fact.text()), &args))
+}
+
+/// Detect facts about a move, most important first
+pub fn detect_facts(pos: &Chess, m: &Move, last_capture: Option<(Square, Role)>) -> Vec<Fact> {
+    let mut facts = Vec::new();
+    let mover = pos.turn();
+
+    let mut after = pos.clone();
+    after.play_unchecked(m);
+
+    if after.is_checkmate() {
+        facts.push(Fact::DeliversMate);
+        return facts;
+    }
+
+    if let Some(mate) = find_mate_in_one(pos) {
+        facts.push(Fact::MissesMate { mate });
+    }
+
+    let taken = material_taken(m);
+    if let Some(threat) = ignored_threat(pos, &after, taken) {
+        facts.push(Fact::IgnoresThreat { threat });
+    }
+
+    if let Some((reply, role, gain)) = best_capture_reply(&after) {
+        if gain - taken >= MATERIAL_THRESHOLD {
+            facts.push(Fact::DropsPiece { role, reply });
+        }
+    }
+
+    let forks_before = pos
+        .clone()
+        .swap_turn()
+        .map(|p| fork_squares(&p))
+        .unwrap_or_default();
+    if let Some(square) = fork_squares(&after)
+        .into_iter()
+        .find(|sq| !forks_before.contains(sq))
+    {
+        facts.push(Fact::AllowsFork { square });
+    }
+
+    if let Some(role) = m.capture() {
+        // A recapture only wins what the exchange nets overall
+        let lost = match last_capture {
+            Some((sq, lost)) if sq == m.to() => tactics::piece_value(lost),
+            _ => 0,
+        };
+        if see::see(pos, m) - lost >= MATERIAL_THRESHOLD {
+            facts.push(Fact::WinsPiece { role });
+        }
+    }
+
+    if trades_good_bishop(pos, &after, m, mover) {
+        facts.push(Fact::TradesGoodBishop);
+    }
+
+    facts
+}
+
+/// Material the move itself takes, counting a promotion's gain
+fn material_taken(m: &Move) -> i32 {
+    let captured = m.capture().map(tactics::piece_value).unwrap_or(0);
+    let promoted = m
+        .promotion()
+        .map(|p| tactics::piece_value(p) - tactics::piece_value(Role::Pawn))
+        .unwrap_or(0);
+    captured + promoted
+}
+
+fn find_mate_in_one(pos: &Chess) -> Option<String> {
+    pos.legal_moves().iter().find_map(|mv| {
+        let mut test = pos.clone();
+        test.play_unchecked(mv);
+        if test.is_checkmate() {
+            Some(SanPlus::from_move(pos.clone(), mv).to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// A threat from before the move that the opponent can still carry out,
+/// net of what the move itself took
+fn ignored_threat(pos: &Chess, after: &Chess, taken: i32) -> Option<String> {
+    threats::immediate_threats(pos).into_iter().find_map(|threat| {
+        let m = after.legal_moves().into_iter().find(|mv| {
+            mv.from().map(|sq| sq.to_string()) == Some(threat.from.clone()) && mv.to().to_string() == threat.to
+        })?;
+        let still_works = match threat.kind {
+            ThreatKind::Mate => {
+                let mut test = after.clone();
+                test.play_unchecked(&m);
+                test.is_checkmate() || threat.mate_in.is_some_and(|n| n > 1 && engine::is_mated_within(&test, n - 1))
+            }
+            _ => see::see(after, &m) - taken >= MATERIAL_THRESHOLD,
+        };
+        still_works.then_some(threat.san)
+    })
+}
+
+/// The most profitable capture for the side to move by static exchange:
+/// (SAN, captured role, net gain)
+fn best_capture_reply(pos: &Chess) -> Option<(String, Role, i32)> {
+    pos.capture_moves()
+        .iter()
+        .filter_map(|c| {
+            let role = c.capture()?;
+            Some((SanPlus::from_move(pos.clone(), c).to_string(), role, see::see(pos, c)))
+        })
+        .max_by_key(|(_, _, gain)| *gain)
+}
+
+fn can_capture_on(pos: &Chess, sq: Square) -> bool {
+    pos.capture_moves().iter().any(|c| c.to() == sq)
+}
+
+/// Squares where the side to move can land a piece that safely attacks two
+/// or more valuable targets
+fn fork_squares(pos: &Chess) -> Vec<Square> {
+    let attacker = pos.turn();
+    let mut squares = Vec::new();
+
+    for mv in pos.legal_moves().iter() {
+        if mv.is_castle() {
+            continue;
+        }
+        let mut test = pos.clone();
+        test.play_unchecked(mv);
+        let board = test.board();
+        let to = mv.to();
+        let role = mv.promotion().unwrap_or(mv.role());
+
+        if tactics::fork_targets(board, to, Piece { color: attacker, role }).len() >= 2
+            && tactics::is_safe(board, to, role, attacker)
+            && !squares.contains(&to)
+        {
+            squares.push(to);
+        }
+    }
+
+    squares
+}
+
+/// A bishop capture that lets the opponent recapture, where the bishop was the
+/// "good" one (fewer of its own pawns on its square color)
+fn trades_good_bishop(pos: &Chess, after: &Chess, m: &Move, mover: Color) -> bool {
+    if m.role() != Role::Bishop || !matches!(m.capture(), Some(Role::Knight | Role::Bishop)) {
+        return false;
+    }
+    let Some(from) = m.from() else {
+        return false;
+    };
+    if !can_capture_on(after, m.to()) {
+        return false;
+    }
+
+    let pawns = pos.board().pawns() & pos.board().by_color(mover);
+    let (same, other) = if from.is_dark() {
+        (Bitboard::DARK_SQUARES, Bitboard::LIGHT_SQUARES)
+    } else {
+        (Bitboard::LIGHT_SQUARES, Bitboard::DARK_SQUARES)
+    };
+    (pawns & same).count() < (pawns & other).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::{fen::Fen, uci::UciMove, CastlingMode};
+
+    fn play(fen: &str, uci: &str) -> (Chess, Move) {
+        let pos: Chess = fen
+            .parse::<Fen>()
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let m = uci.parse::<UciMove>().unwrap().to_move(&pos).unwrap();
+        (pos, m)
+    }
+
+    fn facts(fen: &str, uci: &str) -> Vec<Fact> {
+        let (pos, m) = play(fen, uci);
+        detect_facts(&pos, &m, None)
+    }
+
+    #[test]
+    fn delivers_mate() {
+        assert_eq!(facts("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8"), vec![Fact::DeliversMate]);
+    }
+
+    #[test]
+    fn misses_mate() {
+        let facts = facts("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "g1f1");
+        assert_eq!(facts[0], Fact::MissesMate { mate: "Ra8#".to_string() });
+    }
+
+    #[test]
+    fn ignores_threat() {
+        let facts = facts("r5k1/5ppp/8/8/N7/8/5PPP/6K1 w - - 0 1", "h2h3");
+        assert_eq!(facts[0], Fact::IgnoresThreat { threat: "Rxa4".to_string() });
+    }
+
+    #[test]
+    fn drops_piece() {
+        let facts = facts("3r2k1/8/8/8/3p4/8/5PPP/3Q2K1 w - - 0 1", "d1d4");
+        assert!(facts.contains(&Fact::DropsPiece { role: Role::Queen, reply: "Rxd4".to_string() }));
+    }
+
+    #[test]
+    fn even_trade_drops_nothing() {
+        let facts = facts("3r2k1/8/8/8/3q4/8/5PPP/3Q2K1 w - - 0 1", "d1d4");
+        assert!(!facts.iter().any(|f| matches!(f, Fact::DropsPiece { .. })));
+    }
+
+    #[test]
+    fn allows_fork() {
+        let facts = facts("7k/8/8/8/1n6/8/8/R2QK3 w - - 0 1", "d1g4");
+        assert!(facts.contains(&Fact::AllowsFork { square: Square::C2 }));
+    }
+
+    #[test]
+    fn wins_piece() {
+        let facts = facts("6k1/8/8/8/8/4n3/4QPPP/6K1 w - - 0 1", "e2e3");
+        assert!(facts.contains(&Fact::WinsPiece { role: Role::Knight }));
+    }
+
+    #[test]
+    fn recapture_wins_nothing() {
+        let (pos, m) = play("6k1/8/8/8/8/4n3/4QPPP/6K1 w - - 0 1", "e2e3");
+        let facts = detect_facts(&pos, &m, Some((Square::E3, Role::Bishop)));
+        assert!(!facts.iter().any(|f| matches!(f, Fact::WinsPiece { .. })));
+    }
+
+    #[test]
+    fn trades_good_bishop() {
+        let facts = facts("6k1/5p2/4n3/4P3/2BP4/8/8/6K1 w - - 0 1", "c4e6");
+        assert!(facts.contains(&Fact::TradesGoodBishop));
+    }
+
+    #[test]
+    fn great_move_is_not_blamed() {
+        let (pos, m) = play("r5k1/5ppp/8/8/N7/8/5PPP/6K1 w - - 0 1", "h2h3");
+        assert!(explain_move(&pos, &m, None, &MoveClassification::Great, Language::En).is_none());
+        assert!(explain_move(&pos, &m, None, &MoveClassification::Blunder, Language::En).is_some());
+    }
+
+    #[test]
+    fn best_and_forced_moves_are_not_blamed() {
+        let (pos, m) = play("7k/8/8/8/1n6/8/8/R2QK3 w - - 0 1", "d1g4");
+        for classification in [MoveClassification::Best, MoveClassification::ForcedMove] {
+            assert!(explain_move(&pos, &m, None, &classification, Language::En).is_none());
+        }
+        assert!(explain_move(&pos, &m, None, &MoveClassification::Inaccuracy, Language::En).is_some());
+    }
+
+    #[test]
+    fn blunder_is_not_praised() {
+        let (pos, m) = play("6k1/8/8/8/8/4n3/4QPPP/6K1 w - - 0 1", "e2e3");
+        assert!(explain_move(&pos, &m, None, &MoveClassification::Best, Language::En).is_some());
+        assert!(explain_move(&pos, &m, None, &MoveClassification::Blunder, Language::En).is_none());
+    }
+}
@@ -0,0 +1,54 @@
+use crate::analysis;
+use crate::models::{ExplorerMove, ExplorerPosition};
+use crate::polyglot;
+use shakmaty::{san::SanPlus, CastlingMode, Chess, Position};
+
+/// Games parsed and written per transaction while indexing
+pub const INDEX_BATCH: usize = 500;
+
+/// A move of a stored game, keyed by the position it was played from
+pub struct GameMove {
+    pub ply: usize,
+    pub key: u64,
+    pub san: String,
+    pub uci: String,
+}
+
+/// Every move of a game up to the first one that does not parse. Games set
+/// up from a custom position have no place in the tree.
+pub fn game_moves(pgn: &str) -> Vec<GameMove> {
+    if pgn.lines().any(|l| l.trim_start().starts_with("[FEN ")) {
+        return Vec::new();
+    }
+
+    let mut pos = Chess::default();
+    let mut moves = Vec::new();
+    for (ply, text) in analysis::pgn_san_moves(pgn).iter().enumerate() {
+        let Some(m) = SanPlus::from_ascii(text.as_bytes()).ok().and_then(|s| s.san.to_move(&pos).ok()) else {
+            break;
+        };
+        moves.push(GameMove {
+            ply,
+            key: polyglot::key(&pos),
+            san: SanPlus::from_move(pos.clone(), &m).to_string(),
+            uci: m.to_uci(CastlingMode::Standard).to_string(),
+        });
+        pos.play_unchecked(&m);
+    }
+    moves
+}
+
+/// The explorer view of `pos`, scoring each move for the side that played it
+pub fn position(pos: &Chess, mut moves: Vec<ExplorerMove>) -> ExplorerPosition {
+    let white = pos.turn().is_white();
+    for m in &mut moves {
+        let wins = if white { m.white_wins } else { m.black_wins };
+        let score = (wins as f64 + m.draws as f64 / 2.0) / m.games as f64 * 100.0;
+        m.score = (score * 10.0).round() / 10.0;
+    }
+    ExplorerPosition {
+        fen: analysis::format_fen(pos),
+        games: moves.iter().map(|m| m.games).sum(),
+        moves,
+    }
+}
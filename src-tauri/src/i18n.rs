@@ -0,0 +1,200 @@
+use crate::models::Language;
+use shakmaty::Role;
+
+/// Keys for every offline coaching string. Placeholders are written as `{name}`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Text {
+    // Facts detected on the board
+    DeliversMate,
+    MissesMate,
+    IgnoresThreat,
+    DropsPiece,
+    AllowsFork,
+    WinsPiece,
+    TradesGoodBishop,
+    // Classification labels, used as a prefix to a fact
+    Brilliant,
+    Great,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+    Book,
+    Forced,
+    // After the label of a bad move when no fact was detected
+    BetterMove,
+    // Extra sentence for beginners after a bad move
+    BeginnerTip,
+    // Key moments on the evaluation timeline
+    MomentMissedMate,
+    MomentMissedWin,
+    MomentDecisive,
+    MomentSwing,
+    MomentOnlyMoveMissed,
+    MomentOnlyMoveFound,
+    MomentBrilliant,
+}
+
+/// Look up a string in the catalog for `language`
+pub fn text(language: Language, key: Text) -> &'static str {
+    match language {
+        Language::En => en(key),
+        Language::El => el(key),
+        Language::De => de(key),
+        Language::Es => es(key),
+    }
+}
+
+/// Piece name with its article, as used in "drops {piece}" / "wins {piece}"
+pub fn piece_name(language: Language, role: Role) -> &'static str {
+    match (language, role) {
+        (Language::En, Role::Pawn) => "the pawn",
+        (Language::En, Role::Knight) => "the knight",
+        (Language::En, Role::Bishop) => "the bishop",
+        (Language::En, Role::Rook) => "the rook",
+        (Language::En, Role::Queen) => "the queen",
+        (Language::En, Role::King) => "the king",
+        (Language::El, Role::Pawn) => "το πιόνι",
+        (Language::El, Role::Knight) => "τον ίππο",
+        (Language::El, Role::Bishop) => "τον αξιωματικό",
+        (Language::El, Role::Rook) => "τον πύργο",
+        (Language::El, Role::Queen) => "τη βασίλισσα",
+        (Language::El, Role::King) => "τον βασιλιά",
+        (Language::De, Role::Pawn) => "den Bauern",
+        (Language::De, Role::Knight) => "den Springer",
+        (Language::De, Role::Bishop) => "den Läufer",
+        (Language::De, Role::Rook) => "den Turm",
+        (Language::De, Role::Queen) => "die Dame",
+        (Language::De, Role::King) => "den König",
+        (Language::Es, Role::Pawn) => "el peón",
+        (Language::Es, Role::Knight) => "el caballo",
+        (Language::Es, Role::Bishop) => "el alfil",
+        (Language::Es, Role::Rook) => "la torre",
+        (Language::Es, Role::Queen) => "la dama",
+        (Language::Es, Role::King) => "el rey",
+    }
+}
+
+/// Fill `{name}` placeholders in a template
+pub fn render(template: &str, args: &[(&str, String)]) -> String {
+    let mut out = template.to_string();
+    for (name, value) in args {
+        out = out.replace(&format!("{{{}}}", name), value);
+    }
+    out
+}
+
+fn en(key: Text) -> &'static str {
+    match key {
+        Text::DeliversMate => "{move} delivers checkmate.",
+        Text::MissesMate => "{move} misses {mate}.",
+        Text::IgnoresThreat => "{move} ignores the threat of {threat}.",
+        Text::DropsPiece => "{move} drops {piece} to {reply}.",
+        Text::AllowsFork => "{move} allows a fork on {square}.",
+        Text::WinsPiece => "{move} wins {piece}.",
+        Text::TradesGoodBishop => "{move} trades off the good bishop.",
+        Text::Brilliant => "Brilliant move!",
+        Text::Great => "Great move!",
+        Text::Inaccuracy => "Inaccuracy.",
+        Text::Mistake => "Mistake!",
+        Text::Blunder => "Blunder!",
+        Text::Book => "Theory move.",
+        Text::Forced => "Only legal move.",
+        Text::BetterMove => "{best} was stronger.",
+        Text::BeginnerTip => "Tip: before every move, check what your opponent can capture or attack next.",
+        Text::MomentMissedMate => "{number}. {move} — missed mate in {mate_in}: {line}.",
+        Text::MomentMissedWin => "{number}. {move} — missed {capture}, winning material.",
+        Text::MomentDecisive => "{number}. {move} — the decisive moment: the game was effectively decided here.",
+        Text::MomentSwing => "{number}. {move} — the evaluation swung from {before} to {after}.",
+        Text::MomentOnlyMoveMissed => "{number}. {move} — only {best} held the position here.",
+        Text::MomentOnlyMoveFound => "{number}. {move} — the only move that holds, and it was found.",
+        Text::MomentBrilliant => "{number}. {move} — a brilliant find!",
+    }
+}
+
+fn el(key: Text) -> &'static str {
+    match key {
+        Text::DeliversMate => "Η κίνηση {move} δίνει ματ.",
+        Text::MissesMate => "Η κίνηση {move} παραβλέπει το {mate}.",
+        Text::IgnoresThreat => "Η κίνηση {move} αγνοεί την απειλή {threat}.",
+        Text::DropsPiece => "Η κίνηση {move} χάνει {piece} μετά το {reply}.",
+        Text::AllowsFork => "Η κίνηση {move} επιτρέπει πιρούνι στο {square}.",
+        Text::WinsPiece => "Η κίνηση {move} κερδίζει {piece}.",
+        Text::TradesGoodBishop => "Η κίνηση {move} ανταλλάσσει τον καλό αξιωματικό.",
+        Text::Brilliant => "Εξαιρετική κίνηση!",
+        Text::Great => "Πολύ καλή κίνηση!",
+        Text::Inaccuracy => "Ανακρίβεια.",
+        Text::Mistake => "Λάθος!",
+        Text::Blunder => "Σοβαρό λάθος!",
+        Text::Book => "Κίνηση θεωρίας.",
+        Text::Forced => "Μοναδική νόμιμη κίνηση.",
+        Text::BetterMove => "Το {best} ήταν καλύτερο.",
+        Text::BeginnerTip => {
+            "Συμβουλή: πριν από κάθε κίνηση, έλεγξε τι μπορεί να πάρει ή να απειλήσει ο αντίπαλος."
+        }
+        Text::MomentMissedMate => "{number}. {move} — χάθηκε ματ σε {mate_in}: {line}.",
+        Text::MomentMissedWin => "{number}. {move} — χάθηκε το {capture}, που κέρδιζε υλικό.",
+        Text::MomentDecisive => "{number}. {move} — η κρίσιμη στιγμή: εδώ ουσιαστικά κρίθηκε η παρτίδα.",
+        Text::MomentSwing => "{number}. {move} — η αξιολόγηση άλλαξε από {before} σε {after}.",
+        Text::MomentOnlyMoveMissed => "{number}. {move} — μόνο το {best} κρατούσε τη θέση εδώ.",
+        Text::MomentOnlyMoveFound => "{number}. {move} — η μόνη κίνηση που κρατά, και βρέθηκε.",
+        Text::MomentBrilliant => "{number}. {move} — ένα εξαιρετικό εύρημα!",
+    }
+}
+
+fn de(key: Text) -> &'static str {
+    match key {
+        Text::DeliversMate => "{move} setzt matt.",
+        Text::MissesMate => "{move} übersieht {mate}.",
+        Text::IgnoresThreat => "{move} ignoriert die Drohung {threat}.",
+        Text::DropsPiece => "{move} verliert {piece} nach {reply}.",
+        Text::AllowsFork => "{move} erlaubt eine Gabel auf {square}.",
+        Text::WinsPiece => "{move} gewinnt {piece}.",
+        Text::TradesGoodBishop => "{move} tauscht den guten Läufer.",
+        Text::Brilliant => "Brillanter Zug!",
+        Text::Great => "Starker Zug!",
+        Text::Inaccuracy => "Ungenauigkeit.",
+        Text::Mistake => "Fehler!",
+        Text::Blunder => "Grober Fehler!",
+        Text::Book => "Theoriezug.",
+        Text::Forced => "Einziger legaler Zug.",
+        Text::BetterMove => "{best} war stärker.",
+        Text::BeginnerTip => {
+            "Tipp: Prüfe vor jedem Zug, was dein Gegner als Nächstes schlagen oder angreifen kann."
+        }
+        Text::MomentMissedMate => "{number}. {move} — Matt in {mate_in} verpasst: {line}.",
+        Text::MomentMissedWin => "{number}. {move} — {capture} verpasst, das Material gewinnt.",
+        Text::MomentDecisive => "{number}. {move} — der entscheidende Moment: Hier wurde die Partie entschieden.",
+        Text::MomentSwing => "{number}. {move} — die Bewertung kippte von {before} auf {after}.",
+        Text::MomentOnlyMoveMissed => "{number}. {move} — nur {best} hätte die Stellung gehalten.",
+        Text::MomentOnlyMoveFound => "{number}. {move} — der einzige haltende Zug, und er wurde gefunden.",
+        Text::MomentBrilliant => "{number}. {move} — ein brillanter Fund!",
+    }
+}
+
+fn es(key: Text) -> &'static str {
+    match key {
+        Text::DeliversMate => "{move} da jaque mate.",
+        Text::MissesMate => "{move} pasa por alto {mate}.",
+        Text::IgnoresThreat => "{move} ignora la amenaza de {threat}.",
+        Text::DropsPiece => "{move} pierde {piece} tras {reply}.",
+        Text::AllowsFork => "{move} permite un ataque doble en {square}.",
+        Text::WinsPiece => "{move} gana {piece}.",
+        Text::TradesGoodBishop => "{move} cambia el alfil bueno.",
+        Text::Brilliant => "¡Jugada brillante!",
+        Text::Great => "¡Gran jugada!",
+        Text::Inaccuracy => "Imprecisión.",
+        Text::Mistake => "¡Error!",
+        Text::Blunder => "¡Error grave!",
+        Text::Book => "Jugada de teoría.",
+        Text::Forced => "Única jugada legal.",
+        Text::BetterMove => "{best} era mejor.",
+        Text::BeginnerTip => "Consejo: antes de cada jugada, revisa qué puede capturar o atacar tu rival.",
+        Text::MomentMissedMate => "{number}. {move} — se escapó un mate en {mate_in}: {line}.",
+        Text::MomentMissedWin => "{number}. {move} — se escapó {capture}, que ganaba material.",
+        Text::MomentDecisive => "{number}. {move} — el momento decisivo: aquí se decidió la partida.",
+        Text::MomentSwing => "{number}. {move} — la evaluación pasó de {before} a {after}.",
+        Text::MomentOnlyMoveMissed => "{number}. {move} — solo {best} sostenía la posición aquí.",
+        Text::MomentOnlyMoveFound => "{number}. {move} — la única jugada que sostiene, y se encontró.",
+        Text::MomentBrilliant => "{number}. {move} — ¡un hallazgo brillante!",
+    }
+}
@@ -5,15 +5,32 @@ mod openings;
 mod lessons;
 mod db;
 mod coach;
+mod explain;
+mod i18n;
+mod tactics;
+mod see;
+mod engine;
+mod puzzles;
+mod review;
+mod threats;
+mod eco;
+mod polyglot;
+mod repertoire;
+mod drill;
+mod stats;
+mod explorer;
+mod catalog;
 
 use chess_com::ChessComClient;
 use db::Database;
 use models::*;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use tauri::Manager;
 
 struct AppState {
     db: Arc<Database>,
+    /// Last Polyglot book read, with the path and minimum weight it was opened with
+    book: Mutex<Option<(String, u16, Arc<polyglot::Book>)>>,
 }
 
 #[tauri::command]
@@ -92,17 +109,37 @@ async fn analyze_game_cmd(
     game_url: String,
     end_time: u64,
 ) -> Result<GameAnalysis, String> {
-    // Check if we have cached analysis
+    let style = coach_style(&state)?;
+
+    // Check if we have cached analysis, written in the current format and coach style
     if let Ok(Some(cached_json)) = state.db.get_analysis(&game_url) {
-        if let Ok(cached) = serde_json::from_str::<GameAnalysis>(&cached_json) {
-            return Ok(cached);
+        if let Ok(mut cached) = serde_json::from_str::<GameAnalysis>(&cached_json) {
+            if cached.format_version == analysis::FORMAT_VERSION && cached.comment_style == style {
+                // The repertoire may have changed since the game was analyzed
+                check_repertoire(&state, &mut cached)?;
+                return Ok(cached);
+            }
         }
     }
 
-    // Perform analysis
-    let analysis = analysis::analyze_game(
-        &pgn, &white, &black, &result, &time_control, &time_class, &game_url, end_time,
-    )?;
+    // Perform analysis (engine search, so off the async runtime)
+    let url = game_url.clone();
+    // A broken book setting should not stop analysis
+    let book = opening_book(&state).unwrap_or_else(|e| {
+        eprintln!("Analyzing without the opening book: {}", e);
+        None
+    });
+    let mut analysis = tokio::task::spawn_blocking(move || {
+        analysis::analyze_game(
+            &pgn, &white, &black, &result, &time_control, &time_class, &url, end_time, &style,
+            book.as_deref(),
+        )
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+    ?;
+    keep_user_punishments(&state, &mut analysis)?;
+    check_repertoire(&state, &mut analysis)?;
 
     // Cache the analysis
     if let Ok(json) = serde_json::to_string(&analysis) {
@@ -112,14 +149,134 @@ async fn analyze_game_cmd(
     Ok(analysis)
 }
 
+/// The side the game was fetched for, if it is known
+fn user_color(state: &AppState, analysis: &GameAnalysis) -> Result<Option<String>, String> {
+    let username = state.db.get_game_username(&analysis.game_url)?;
+    Ok(username.and_then(|u| {
+        if analysis.white.eq_ignore_ascii_case(&u) {
+            Some("white".to_string())
+        } else if analysis.black.eq_ignore_ascii_case(&u) {
+            Some("black".to_string())
+        } else {
+            None
+        }
+    }))
+}
+
+/// Keep only the opponent's errors the user failed to punish, when the
+/// user's side is known
+fn keep_user_punishments(state: &AppState, analysis: &mut GameAnalysis) -> Result<(), String> {
+    if let Some(color) = user_color(state, analysis)? {
+        analysis.missed_punishments.retain(|p| p.color == color);
+        analysis.summary.missed_punishments = analysis.missed_punishments.len() as u32;
+    }
+    Ok(())
+}
+
+/// Find where the game left the user's repertoire and record it
+fn check_repertoire(state: &AppState, analysis: &mut GameAnalysis) -> Result<(), String> {
+    let Some(color) = user_color(state, analysis)? else {
+        return Ok(());
+    };
+    let edges = state.db.get_repertoire_edges(&color)?;
+    let deviation = repertoire::find_deviation(&color, &edges, &analysis.moves);
+
+    match &deviation {
+        Some(d) => {
+            let key = polyglot::key(&analysis::parse_fen(&d.fen)?);
+            let line: Vec<&str> = analysis.moves[..d.move_index].iter().map(|m| m.san.as_str()).collect();
+            state
+                .db
+                .save_repertoire_deviation(&analysis.game_url, &color, &analysis.date, Some((key, d, &line.join(" "))))?
+        }
+        None => state.db.save_repertoire_deviation(&analysis.game_url, &color, &analysis.date, None)?,
+    }
+
+    analysis.repertoire_deviation = deviation;
+    Ok(())
+}
+
+/// The Polyglot book from the `opening_book_path` setting, if one is set.
+/// `opening_book_min_weight` is the weight a move needs to count as theory.
+/// The file is read once and kept until either setting changes.
+fn opening_book(state: &AppState) -> Result<Option<Arc<polyglot::Book>>, String> {
+    let path = state.db.get_setting("opening_book_path")?.unwrap_or_default();
+    if path.trim().is_empty() {
+        return Ok(None);
+    }
+    let min_weight = state
+        .db
+        .get_setting("opening_book_min_weight")?
+        .and_then(|w| w.trim().parse().ok())
+        .unwrap_or(polyglot::DEFAULT_MIN_WEIGHT);
+
+    let path = path.trim();
+    let mut cached = state.book.lock().map_err(|e| format!("Lock error: {}", e))?;
+    if let Some((cached_path, cached_weight, book)) = cached.as_ref() {
+        if cached_path == path && *cached_weight == min_weight {
+            return Ok(Some(book.clone()));
+        }
+    }
+    let book = Arc::new(polyglot::Book::open(path, min_weight)?);
+    *cached = Some((path.to_string(), min_weight, book.clone()));
+    Ok(Some(book))
+}
+
+#[tauri::command]
+async fn get_book_moves(
+    state: tauri::State<'_, AppState>,
+    fen: String,
+) -> Result<Vec<BookMove>, String> {
+    let pos = analysis::parse_fen(&fen)?;
+    Ok(opening_book(&state)?.map(|b| b.moves(&pos)).unwrap_or_default())
+}
+
+#[tauri::command]
+fn get_hanging_pieces(fen: String) -> Result<Vec<HangingPiece>, String> {
+    see::hanging_pieces_in_fen(&fen)
+}
+
 #[tauri::command]
-fn get_openings(category: Option<String>) -> Vec<Opening> {
+fn get_threats(fen: String) -> Result<Vec<Threat>, String> {
+    Ok(threats::detect_threats(&analysis::parse_fen(&fen)?))
+}
+
+#[tauri::command]
+fn get_openings(category: Option<OpeningCategory>) -> Vec<Opening> {
     match category {
-        Some(cat) => openings::get_openings_by_category(&cat),
+        Some(cat) => openings::get_openings_by_category(cat),
         None => openings::get_all_openings(),
     }
 }
 
+/// Opening families with their variations, for navigating the catalog
+#[tauri::command]
+fn get_opening_tree(category: Option<OpeningCategory>) -> Vec<OpeningNode> {
+    openings::get_opening_tree(category)
+}
+
+#[tauri::command]
+fn get_opening_variations(id: String) -> Vec<Opening> {
+    openings::get_opening_variations(&id)
+}
+
+/// The opening a position belongs to, whatever the move order
+#[tauri::command]
+fn identify_opening(fen: String) -> Result<Option<IdentifiedOpening>, String> {
+    Ok(openings::identify_opening(&analysis::parse_fen(&fen)?))
+}
+
+#[tauri::command]
+fn get_opening_by_eco(eco: String) -> Option<NamedOpening> {
+    openings::get_opening_by_eco(&eco)
+}
+
+/// Openings whose names loosely match `query`, best first
+#[tauri::command]
+fn search_openings(query: String, limit: usize) -> Vec<NamedOpening> {
+    openings::search_openings(&query, limit)
+}
+
 #[tauri::command]
 fn get_lessons(category: Option<String>) -> Vec<Lesson> {
     match category {
@@ -154,41 +311,609 @@ async fn get_coach_comment(
     played_move: String,
     best_move: Option<String>,
     classification: String,
-    color: String,
-    move_number: u32,
 ) -> Result<String, String> {
-    // Check cache first
-    if let Ok(Some(cached)) = state.db.get_coach_comment(&game_url, move_index) {
+    let key = comment_key(&state, fen, played_move, best_move, classification)?;
+
+    // Check cache first (shared by every game reaching the same position)
+    if let Ok(Some(cached)) = state.db.get_coach_comment(&key) {
+        let _ = state.db.link_coach_comment(&key, &game_url, move_index);
         return Ok(cached);
     }
 
-    // Generate coaching comment via Gemini CLI (blocking)
-    let fen_clone = fen.clone();
-    let played_clone = played_move.clone();
-    let best_clone = best_move.clone();
-    let class_clone = classification.clone();
-    let color_clone = color.clone();
-
-    let comment = tokio::task::spawn_blocking(move || {
-        coach::get_coaching_comment(
-            &fen_clone,
-            &played_clone,
-            best_clone.as_deref(),
-            &class_clone,
-            &color_clone,
-            move_number,
-        )
+    generate_coach_comment(&state, key, &game_url, move_index).await
+}
+
+#[tauri::command]
+async fn regenerate_coach_comment(
+    state: tauri::State<'_, AppState>,
+    game_url: String,
+    move_index: usize,
+    fen: String,
+    played_move: String,
+    best_move: Option<String>,
+    classification: String,
+) -> Result<String, String> {
+    let key = comment_key(&state, fen, played_move, best_move, classification)?;
+    generate_coach_comment(&state, key, &game_url, move_index).await
+}
+
+#[tauri::command]
+async fn purge_coach_cache(
+    state: tauri::State<'_, AppState>,
+    game_url: Option<String>,
+) -> Result<usize, String> {
+    state.db.purge_coach_comments(game_url.as_deref())
+}
+
+fn comment_key(
+    state: &AppState,
+    fen: String,
+    played_move: String,
+    best_move: Option<String>,
+    classification: String,
+) -> Result<coach::CommentKey, String> {
+    Ok(coach::CommentKey {
+        fen,
+        played_move,
+        best_move,
+        classification,
+        model: state.db.get_setting("coach_model")?.unwrap_or_default(),
+        style: coach_style(state)?,
+        prompt: coach::Prompt::Move,
     })
-    .await
-    .map_err(|e| format!("Task error: {}", e))?
-    ?;
+}
+
+/// Coach language and level from the `coach_language` / `coach_level` settings
+fn coach_style(state: &AppState) -> Result<CoachStyle, String> {
+    let language = state.db.get_setting("coach_language")?.unwrap_or_default();
+    let level = state.db.get_setting("coach_level")?.unwrap_or_default();
+    Ok(CoachStyle {
+        language: Language::from_code(&language),
+        level: CoachLevel::from_code(&level),
+    })
+}
+
+/// Generate a coaching comment via Gemini CLI (blocking) and cache it
+async fn generate_coach_comment(
+    state: &AppState,
+    key: coach::CommentKey,
+    game_url: &str,
+    move_index: usize,
+) -> Result<String, String> {
+    let key_clone = key.clone();
+    let comment = tokio::task::spawn_blocking(move || coach::get_coaching_comment(&key_clone))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+        ?;
 
     // Cache the result
-    let _ = state.db.save_coach_comment(&game_url, move_index, &comment);
+    let _ = state.db.save_coach_comment(&key, game_url, move_index, &comment);
 
     Ok(comment)
 }
 
+#[tauri::command]
+async fn get_game_report(
+    state: tauri::State<'_, AppState>,
+    game_url: String,
+) -> Result<GameReport, String> {
+    let style = coach_style(&state)?;
+
+    // Check cache first
+    if let Ok(Some(cached_json)) = state.db.get_game_report(&game_url) {
+        if let Ok(cached) = serde_json::from_str::<GameReport>(&cached_json) {
+            if cached.style == style {
+                return Ok(cached);
+            }
+        }
+    }
+
+    let analysis = load_analysis(&state, &game_url)?;
+
+    let model = state.db.get_setting("coach_model")?.unwrap_or_default();
+    let report = tokio::task::spawn_blocking(move || coach::get_game_report(&analysis, &model, &style))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+        ?;
+
+    // Cache the result
+    if let Ok(json) = serde_json::to_string(&report) {
+        let _ = state.db.save_game_report(&game_url, &json);
+    }
+
+    Ok(report)
+}
+
+/// Coach every key moment of an analyzed game with a single provider call.
+/// Cached comments from either prompt are reused; moves the batch response
+/// misses (or all of them, if it is malformed) fall back to one call per move.
+/// Each comment is cached under the prompt that wrote it.
+#[tauri::command]
+async fn get_key_moment_comments(
+    state: tauri::State<'_, AppState>,
+    game_url: String,
+) -> Result<Vec<CoachComment>, String> {
+    let analysis = load_analysis(&state, &game_url)?;
+
+    let mut comments = Vec::new();
+    let mut pending = Vec::new();
+    for moment in &analysis.key_moments {
+        let Some(m) = analysis.moves.get(moment.move_index) else {
+            continue;
+        };
+        let key = comment_key(
+            &state,
+            m.fen_before.clone(),
+            m.san.clone(),
+            m.best_move_san.clone(),
+            moment.classification.clone(),
+        )?;
+
+        let cached = [coach::Prompt::Batch, coach::Prompt::Move].into_iter().find_map(|prompt| {
+            let key = coach::CommentKey { prompt, ..key.clone() };
+            let comment = state.db.get_coach_comment(&key).ok().flatten()?;
+            Some((key, comment))
+        });
+
+        if let Some((cached_key, cached)) = cached {
+            let _ = state.db.link_coach_comment(&cached_key, &game_url, moment.move_index);
+            comments.push(CoachComment {
+                move_index: moment.move_index,
+                comment: cached,
+            });
+        } else {
+            pending.push(coach::BatchItem {
+                move_index: moment.move_index,
+                key,
+            });
+        }
+    }
+
+    if !pending.is_empty() {
+        let model = state.db.get_setting("coach_model")?.unwrap_or_default();
+        let style = coach_style(&state)?;
+        let items = pending.clone();
+
+        let generated = tokio::task::spawn_blocking(move || {
+            let batch = coach::get_batch_comments(&items, &model, &style).unwrap_or_else(|e| {
+                eprintln!("Batch coaching failed, falling back to per-move calls: {}", e);
+                Vec::new()
+            });
+            let mut generated: Vec<_> = batch
+                .into_iter()
+                .map(|(idx, comment)| (idx, comment, coach::Prompt::Batch))
+                .collect();
+
+            let mut last_error = None;
+            for item in &items {
+                if generated.iter().any(|(idx, _, _)| *idx == item.move_index) {
+                    continue;
+                }
+                match coach::get_coaching_comment(&item.key) {
+                    Ok(comment) => generated.push((item.move_index, comment, coach::Prompt::Move)),
+                    Err(e) => last_error = Some(e),
+                }
+            }
+
+            match last_error {
+                Some(e) if generated.is_empty() => Err(e),
+                _ => Ok(generated),
+            }
+        })
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+        ?;
+
+        for (move_index, comment, prompt) in generated {
+            if let Some(item) = pending.iter().find(|i| i.move_index == move_index) {
+                let key = coach::CommentKey { prompt, ..item.key.clone() };
+                let _ = state.db.save_coach_comment(&key, &game_url, move_index, &comment);
+            }
+            comments.push(CoachComment { move_index, comment });
+        }
+    }
+
+    comments.sort_by_key(|c| c.move_index);
+    Ok(comments)
+}
+
+/// Turn the user's Mistakes and Blunders in an analyzed game into puzzles
+#[tauri::command]
+async fn generate_puzzles(
+    state: tauri::State<'_, AppState>,
+    game_url: String,
+) -> Result<Vec<Puzzle>, String> {
+    let analysis = load_analysis(&state, &game_url)?;
+
+    // Only the side the game was fetched for; both if it is unknown
+    let color = user_color(&state, &analysis)?;
+
+    let puzzles = tokio::task::spawn_blocking(move || puzzles::extract_puzzles(&analysis, color.as_deref()))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?;
+
+    let saved = state.db.save_puzzles(&puzzles)?;
+    eprintln!("Saved {} new puzzles from {}", saved, game_url);
+
+    // Every puzzle also goes on the review schedule
+    let puzzles = state.db.get_puzzles(Some(&game_url), None)?;
+    let now = chrono::Utc::now().timestamp();
+    for p in &puzzles {
+        let title = format!("{}. Find better than {}", p.move_index / 2 + 1, p.played_move);
+        state
+            .db
+            .add_review_item(ReviewKind::Puzzle, &p.id.to_string(), &title, &p.fen, &p.solution, now)?;
+    }
+
+    Ok(puzzles)
+}
+
+#[tauri::command]
+async fn get_puzzles(
+    state: tauri::State<'_, AppState>,
+    game_url: Option<String>,
+    limit: usize,
+) -> Result<Vec<Puzzle>, String> {
+    state.db.get_puzzles(game_url.as_deref(), Some(limit))
+}
+
+/// Validate one solver move of a puzzle attempt. `step` is the index of the
+/// move in the solution; the attempt is recorded once it is solved or failed.
+#[tauri::command]
+async fn check_puzzle_move(
+    state: tauri::State<'_, AppState>,
+    puzzle_id: i64,
+    step: usize,
+    uci: String,
+) -> Result<PuzzleMoveResult, String> {
+    let puzzle = state
+        .db
+        .get_puzzle(puzzle_id)?
+        .ok_or_else(|| format!("Puzzle {} not found", puzzle_id))?;
+
+    let result = puzzles::check_move(&puzzle, step, &uci)?;
+    if result.solved || !result.correct {
+        state.db.record_puzzle_attempt(puzzle_id, result.solved)?;
+    }
+    Ok(result)
+}
+
+#[tauri::command]
+async fn get_due_reviews(
+    state: tauri::State<'_, AppState>,
+    limit: usize,
+) -> Result<Vec<ReviewItem>, String> {
+    state.db.get_due_reviews(chrono::Utc::now().timestamp(), limit)
+}
+
+/// Grade a review and return the item with its next due date
+#[tauri::command]
+async fn submit_review(
+    state: tauri::State<'_, AppState>,
+    item_id: i64,
+    grade: ReviewGrade,
+) -> Result<ReviewItem, String> {
+    let item = state
+        .db
+        .get_review_item(item_id)?
+        .ok_or_else(|| format!("Review item {} not found", item_id))?;
+
+    let now = chrono::Utc::now().timestamp();
+    let next = review::schedule(&item, grade, now);
+    state.db.save_review(&item, &next, grade, now)?;
+    Ok(next)
+}
+
+#[tauri::command]
+async fn add_review_item(
+    state: tauri::State<'_, AppState>,
+    kind: ReviewKind,
+    source_id: String,
+    title: String,
+    fen: String,
+    solution: Vec<String>,
+) -> Result<Option<i64>, String> {
+    analysis::parse_fen(&fen)?;
+    state
+        .db
+        .add_review_item(kind, &source_id, &title, &fen, &solution, chrono::Utc::now().timestamp())
+}
+
+/// Review counts for the current local day
+#[tauri::command]
+async fn get_review_stats(state: tauri::State<'_, AppState>) -> Result<ReviewStats, String> {
+    let now = chrono::Local::now();
+    let day_start = now
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|d| d.and_local_timezone(chrono::Local).earliest())
+        .map(|d| d.timestamp())
+        .unwrap_or_else(|| now.timestamp());
+    state
+        .db
+        .get_review_stats(now.timestamp(), day_start, day_start + 24 * 60 * 60)
+}
+
+/// Add a line of SAN moves from the initial position to a repertoire.
+/// Returns how many moves were new.
+#[tauri::command]
+async fn add_repertoire_line(
+    state: tauri::State<'_, AppState>,
+    color: String,
+    moves: Vec<String>,
+) -> Result<usize, String> {
+    repertoire::check_color(&color)?;
+    let edges = repertoire::line_edges(&moves)?;
+    state.db.add_repertoire_moves(&color, &edges)
+}
+
+/// Remove the last move of a line, with whatever only it led to
+#[tauri::command]
+async fn remove_repertoire_line(
+    state: tauri::State<'_, AppState>,
+    color: String,
+    moves: Vec<String>,
+) -> Result<usize, String> {
+    repertoire::check_color(&color)?;
+    let edges = repertoire::line_edges(&moves)?;
+    let last = edges.last().ok_or_else(|| "No moves given".to_string())?;
+    state.db.remove_repertoire_move(&color, last.key, &last.uci)
+}
+
+#[tauri::command]
+async fn get_repertoire_node(
+    state: tauri::State<'_, AppState>,
+    color: String,
+    fen: String,
+) -> Result<RepertoireNode, String> {
+    repertoire::check_color(&color)?;
+    let pos = analysis::parse_fen(&fen)?;
+    let key = polyglot::key(&pos);
+    Ok(RepertoireNode {
+        fen: analysis::format_fen(&pos),
+        note: state.db.get_repertoire_notes(&color)?.remove(&key),
+        user_to_move: repertoire::user_to_move(&color, &pos),
+        moves: state.db.get_repertoire_moves(&color, key)?,
+    })
+}
+
+/// Comment on a repertoire position; an empty note removes it
+#[tauri::command]
+async fn annotate_repertoire_position(
+    state: tauri::State<'_, AppState>,
+    color: String,
+    fen: String,
+    note: String,
+) -> Result<(), String> {
+    repertoire::check_color(&color)?;
+    let pos = analysis::parse_fen(&fen)?;
+    state
+        .db
+        .set_repertoire_note(&color, polyglot::key(&pos), &analysis::format_fen(&pos), &note)
+}
+
+/// Merge every line of a PGN, variations and comments included, into a repertoire
+#[tauri::command]
+async fn import_repertoire_pgn(
+    state: tauri::State<'_, AppState>,
+    color: String,
+    pgn: String,
+) -> Result<RepertoireImport, String> {
+    repertoire::check_color(&color)?;
+    let import = repertoire::import_pgn(&pgn)?;
+    let moves_added = state.db.add_repertoire_moves(&color, &import.edges)?;
+    for note in &import.notes {
+        state.db.set_repertoire_note(&color, note.key, &note.fen, &note.text)?;
+    }
+    Ok(RepertoireImport {
+        moves_added,
+        notes_added: import.notes.len(),
+    })
+}
+
+#[tauri::command]
+async fn export_repertoire_pgn(
+    state: tauri::State<'_, AppState>,
+    color: String,
+) -> Result<String, String> {
+    repertoire::check_color(&color)?;
+    let edges = state.db.get_repertoire_edges(&color)?;
+    let notes = state.db.get_repertoire_notes(&color)?;
+    Ok(repertoire::export_pgn(&color, &edges, &notes))
+}
+
+/// Results, accuracy and book exits of the user's games by opening and color
+#[tauri::command]
+async fn get_opening_stats(
+    state: tauri::State<'_, AppState>,
+    username: String,
+    grouping: OpeningGrouping,
+    color: Option<String>,
+) -> Result<Vec<OpeningStats>, String> {
+    let games = state.db.get_games_with_analysis(&username)?;
+    let mut stats = tokio::task::spawn_blocking(move || {
+        let games: Vec<(ChessComGame, Option<GameAnalysis>)> = games
+            .into_iter()
+            .map(|(game, json)| {
+                let cached = json
+                    .and_then(|j| serde_json::from_str::<GameAnalysis>(&j).ok())
+                    .filter(|a| a.format_version == analysis::FORMAT_VERSION);
+                (game, cached)
+            })
+            .collect();
+        stats::opening_stats(&username, &games, grouping)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?;
+
+    if let Some(color) = color {
+        stats.retain(|s| s.color == color);
+    }
+    Ok(stats)
+}
+
+/// Add games stored since the last lookup to the position index
+async fn index_games(state: &AppState) -> Result<(), String> {
+    loop {
+        let games = state.db.get_unindexed_games(explorer::INDEX_BATCH)?;
+        if games.is_empty() {
+            return Ok(());
+        }
+        let indexed = tokio::task::spawn_blocking(move || {
+            games
+                .into_iter()
+                .map(|(url, pgn)| {
+                    let moves = pgn.as_deref().map(explorer::game_moves).unwrap_or_default();
+                    (url, moves)
+                })
+                .collect::<Vec<_>>()
+        })
+        .await
+        .map_err(|e| format!("Task error: {}", e))?;
+        state.db.save_game_positions(&indexed)?;
+    }
+}
+
+/// Moves played from a position in the stored games, whatever the move order
+#[tauri::command]
+async fn explore_position(
+    state: tauri::State<'_, AppState>,
+    fen: String,
+    filter: ExplorerFilter,
+) -> Result<ExplorerPosition, String> {
+    let pos = analysis::parse_fen(&fen)?;
+    index_games(&state).await?;
+    let moves = state.db.explore_position(polyglot::key(&pos), &filter)?;
+    Ok(explorer::position(&pos, moves))
+}
+
+/// Drill lines of one source with their results so far, both colors unless `color` is given
+fn drill_lines(state: &AppState, source: DrillSource, color: Option<&str>) -> Result<Vec<DrillLine>, String> {
+    let mut lines = match source {
+        DrillSource::Repertoire => {
+            let mut lines = Vec::new();
+            for side in ["white", "black"] {
+                lines.extend(drill::repertoire_lines(side, &state.db.get_repertoire_edges(side)?));
+            }
+            lines
+        }
+        DrillSource::Catalog => drill::catalog_lines(&openings::get_all_openings()),
+    };
+    if let Some(color) = color {
+        lines.retain(|l| l.color == color);
+    }
+
+    let mut stats = state.db.get_drill_stats()?;
+    for line in &mut lines {
+        if let Some(s) = stats.remove(&line.id) {
+            line.stats = s;
+        }
+    }
+    Ok(lines)
+}
+
+/// A drill line and the lines of the same side it may transpose into
+fn find_drill_line(state: &AppState, line_id: &str) -> Result<(DrillLine, Vec<DrillLine>), String> {
+    let source = match line_id.split(':').next() {
+        Some("repertoire") => DrillSource::Repertoire,
+        Some("catalog") => DrillSource::Catalog,
+        _ => return Err(format!("Unknown drill line: {}", line_id)),
+    };
+    let mut lines = drill_lines(state, source, None)?;
+    let target = lines
+        .iter()
+        .find(|l| l.id == line_id)
+        .cloned()
+        .ok_or_else(|| format!("Drill line {} not found", line_id))?;
+    lines.retain(|l| l.color == target.color);
+    Ok((target, lines))
+}
+
+#[tauri::command]
+async fn get_drill_lines(
+    state: tauri::State<'_, AppState>,
+    source: DrillSource,
+    color: Option<String>,
+) -> Result<Vec<DrillLine>, String> {
+    if let Some(color) = &color {
+        repertoire::check_color(color)?;
+    }
+    drill_lines(&state, source, color.as_deref())
+}
+
+/// Set up the board for a drill, with the app's first move when the user has Black
+#[tauri::command]
+async fn start_drill(state: tauri::State<'_, AppState>, line_id: String) -> Result<DrillStep, String> {
+    let (target, lines) = find_drill_line(&state, &line_id)?;
+    drill::start(&target, &lines)
+}
+
+/// Validate the user's move in a drill and play the app's answer. Every move
+/// counts towards the line's accuracy, and a wrong one is scheduled for review.
+#[tauri::command]
+async fn check_drill_move(
+    state: tauri::State<'_, AppState>,
+    line_id: String,
+    fen: String,
+    uci: String,
+) -> Result<DrillStep, String> {
+    let (target, lines) = find_drill_line(&state, &line_id)?;
+    let step = drill::check_move(&target, &lines, &fen, &uci)?;
+
+    let now = chrono::Utc::now().timestamp();
+    state.db.record_drill_move(&line_id, step.correct, step.finished, now)?;
+    if !step.correct {
+        let key = polyglot::key(&analysis::parse_fen(&fen)?);
+        let title = format!("{}: play the prepared move", target.name);
+        state.db.add_review_item(
+            ReviewKind::Opening,
+            &format!("{}@{:016x}", line_id, key),
+            &title,
+            &step.fen,
+            &step.expected_uci[..step.expected_uci.len().min(1)],
+            now,
+        )?;
+    }
+    Ok(step)
+}
+
+/// Repertoire positions where the user most often played something else,
+/// with the moves prepared there now
+#[tauri::command]
+async fn get_forgotten_lines(
+    state: tauri::State<'_, AppState>,
+    limit: usize,
+) -> Result<Vec<ForgottenLine>, String> {
+    let mut lines = Vec::new();
+    for (key, mut line) in state.db.get_forgotten_lines(limit)? {
+        line.expected = state
+            .db
+            .get_repertoire_moves(&line.color, key)?
+            .into_iter()
+            .map(|m| m.san)
+            .collect();
+        // A move added to the repertoire since is no longer a mistake
+        if !line.expected.contains(&line.played) {
+            lines.push(line);
+        }
+    }
+    Ok(lines)
+}
+
+/// Load a game's cached analysis; coach features need the game analyzed first
+fn load_analysis(state: &AppState, game_url: &str) -> Result<GameAnalysis, String> {
+    let analysis_json = state
+        .db
+        .get_analysis(game_url)?
+        .ok_or_else(|| "Game has not been analyzed yet".to_string())?;
+    let analysis: GameAnalysis =
+        serde_json::from_str(&analysis_json).map_err(|e| format!("Failed to parse cached analysis: {}", e))?;
+    if analysis.format_version != analysis::FORMAT_VERSION {
+        return Err("Game analysis is out of date, analyze the game again".to_string());
+    }
+    Ok(analysis)
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -199,10 +924,12 @@ pub fn run() {
                 .app_data_dir()
                 .expect("Failed to get app data dir");
 
+            catalog::init(&app_dir);
             let db = Database::new(app_dir).expect("Failed to initialize database");
 
             app.manage(AppState {
                 db: Arc::new(db),
+                book: Mutex::new(None),
             });
 
             Ok(())
@@ -214,11 +941,42 @@ pub fn run() {
             get_saved_games,
             get_game_count,
             analyze_game_cmd,
+            get_hanging_pieces,
+            get_book_moves,
+            get_threats,
             get_openings,
+            get_opening_tree,
+            get_opening_variations,
             get_lessons,
             get_setting,
             set_setting,
             get_coach_comment,
+            regenerate_coach_comment,
+            purge_coach_cache,
+            get_game_report,
+            get_key_moment_comments,
+            generate_puzzles,
+            get_puzzles,
+            check_puzzle_move,
+            get_due_reviews,
+            submit_review,
+            add_review_item,
+            get_review_stats,
+            add_repertoire_line,
+            remove_repertoire_line,
+            get_repertoire_node,
+            annotate_repertoire_position,
+            import_repertoire_pgn,
+            export_repertoire_pgn,
+            get_forgotten_lines,
+            get_drill_lines,
+            start_drill,
+            check_drill_move,
+            get_opening_stats,
+            explore_position,
+            identify_opening,
+            get_opening_by_eco,
+            search_openings,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -112,6 +112,32 @@ pub struct GameAnalysis {
     pub summary: GameSummary,
     #[serde(default)]
     pub key_moments: Vec<KeyMoment>,
+    #[serde(default)]
+    pub missed_punishments: Vec<MissedPunishment>,
+    // Where the game left the user's repertoire, if it did
+    #[serde(default)]
+    pub repertoire_deviation: Option<RepertoireDeviation>,
+    // Style the offline comments were written in
+    #[serde(default)]
+    pub comment_style: CoachStyle,
+    // Format the analysis was saved in, see `analysis::FORMAT_VERSION`
+    #[serde(default)]
+    pub format_version: u32,
+}
+
+/// An opponent's error that the reply failed to exploit
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MissedPunishment {
+    pub move_index: usize, // the reply
+    pub move_number: u32,
+    pub color: String, // side that missed the chance
+    pub error: String, // SAN of the opponent's mistake
+    pub played: String,
+    pub punishment: String, // SAN of the engine's best reply
+    // Evaluations in pawns, positive = white advantage
+    pub eval_before_error: f64,
+    pub eval_available: f64,
+    pub eval_after: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -122,7 +148,35 @@ pub struct KeyMoment {
     pub color: String,
     pub classification: String,
     pub description: String,
-    pub severity: String, // "critical", "major", "notable"
+    pub severity: Severity,
+    #[serde(default)]
+    pub reason: MomentReason,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Critical,
+    Major,
+    Notable,
+}
+
+/// Why a move is a key moment
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum MomentReason {
+    // A forced mate or decisive capture was available and not played
+    MissedMate,
+    MissedWin,
+    // The move after which the result was no longer in doubt
+    Decisive,
+    // Win probability swung against the player
+    #[default]
+    Swing,
+    // Only one move held and the player missed it
+    OnlyMoveMissed,
+    // Only one move held and the player found it
+    OnlyMoveFound,
+    Brilliant,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -143,6 +197,225 @@ pub struct MoveAnalysis {
     pub fen_before: String,
     #[serde(default)]
     pub eval_score: f64, // centipawns, positive = white advantage
+    #[serde(default)]
+    pub motifs: Vec<Motif>,
+    // A forced mate or decisive capture that was available and not played
+    #[serde(default)]
+    pub missed_win: Option<MissedWin>,
+    // What the opponent threatened before this move
+    #[serde(default)]
+    pub threats_before: Vec<Threat>,
+    // The engine's top moves in the position before this move
+    #[serde(default)]
+    pub candidates: Vec<Candidate>,
+    /// What the opening book recommends where the game left it
+    #[serde(default)]
+    pub book_alternatives: Vec<BookMove>,
+}
+
+/// One of the engine's top moves, with its line
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Candidate {
+    pub san: String,
+    pub from: String,
+    pub to: String,
+    pub eval: f64, // pawns, positive = white advantage
+    pub mate_in: Option<i32>, // positive = white mates
+    pub pv: Vec<String>, // SAN, starting with this move
+}
+
+/// A move from the Polyglot opening book
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BookMove {
+    pub san: String,
+    pub uci: String,
+    pub from: String,
+    pub to: String,
+    pub weight: u16,
+    pub share: f64, // percent of the position's total weight
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MissedWin {
+    pub kind: MissedWinKind,
+    pub line: Vec<String>, // SAN, starting with the move that should have been played
+    pub mate_in: Option<u32>,
+    pub gain: i32, // centipawns won by the capture
+}
+
+/// A move the opponent would play if given a free move
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Threat {
+    pub kind: ThreatKind,
+    pub san: String,
+    // Arrow data for the threat overlay
+    pub from: String,
+    pub to: String,
+    pub line: Vec<String>, // SAN, starting with the threatening move
+    pub gain: i32, // centipawns
+    pub mate_in: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ThreatKind {
+    Mate,
+    Capture,
+    Tactic,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum MissedWinKind {
+    Mate,
+    Material,
+}
+
+/// A tactical pattern created by a move
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Motif {
+    pub kind: MotifKind,
+    // The key piece: forking/pinned/overloaded/trapped piece, discovering slider or king
+    pub square: String,
+    // The pieces involved on the other side of the pattern
+    pub targets: Vec<String>,
+}
+
+/// A piece the opponent can win material against, by static exchange evaluation
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HangingPiece {
+    pub square: String,
+    pub role: String,
+    pub color: String,
+    pub loss: i32, // centipawns
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum MotifKind {
+    Fork,
+    AbsolutePin,
+    RelativePin,
+    Skewer,
+    DiscoveredAttack,
+    DiscoveredCheck,
+    OverloadedDefender,
+    BackRankWeakness,
+    TrappedPiece,
+}
+
+/// A position from the user's own games with a single winning line
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Puzzle {
+    pub id: i64,
+    pub game_url: String,
+    pub move_index: usize,
+    pub fen: String,
+    pub color: String, // side to solve
+    pub played_move: String, // what was played in the game instead
+    pub solution: Vec<String>, // UCI, solver and opponent moves alternating
+    pub solution_san: Vec<String>,
+    pub themes: Vec<MotifKind>,
+    pub mate_in: Option<u32>,
+    pub rating: i32,
+    pub attempts: u32,
+    pub solved: u32,
+}
+
+/// Outcome of one solver move in a puzzle attempt
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PuzzleMoveResult {
+    pub correct: bool,
+    pub solved: bool,
+    // Opponent's answer to play on the board when the puzzle goes on
+    pub reply: Option<String>,
+    pub reply_san: Option<String>,
+    // The solution move, after a wrong answer
+    pub expected: Option<String>,
+}
+
+// Spaced repetition models
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewKind {
+    Puzzle,
+    Mistake,
+    Opening,
+    Lesson,
+}
+
+impl ReviewKind {
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "puzzle" => Some(Self::Puzzle),
+            "mistake" => Some(Self::Mistake),
+            "opening" => Some(Self::Opening),
+            "lesson" => Some(Self::Lesson),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        match self {
+            Self::Puzzle => "puzzle",
+            Self::Mistake => "mistake",
+            Self::Opening => "opening",
+            Self::Lesson => "lesson",
+        }
+    }
+}
+
+/// A position to practise again, scheduled SM-2 style
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReviewItem {
+    pub id: i64,
+    pub kind: ReviewKind,
+    pub source_id: String, // puzzle id, game move, repertoire line or lesson
+    pub title: String,
+    pub fen: String,
+    pub solution: Vec<String>, // UCI
+    pub ease: f64,
+    pub interval_days: u32,
+    pub repetitions: u32,
+    pub lapses: u32,
+    pub due_at: i64, // unix seconds
+}
+
+/// How well a review went, Anki style
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewGrade {
+    Again,
+    Hard,
+    Good,
+    Easy,
+}
+
+impl ReviewGrade {
+    /// SM-2 response quality (0-5)
+    pub fn quality(&self) -> u8 {
+        match self {
+            Self::Again => 1,
+            Self::Hard => 3,
+            Self::Good => 4,
+            Self::Easy => 5,
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        match self {
+            Self::Again => "again",
+            Self::Hard => "hard",
+            Self::Good => "good",
+            Self::Easy => "easy",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReviewStats {
+    pub total_items: u32,
+    pub due_now: u32,
+    pub due_today: u32,
+    pub reviewed_today: u32,
+    pub failed_today: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -186,11 +459,119 @@ pub struct GameSummary {
     pub blunders: u32,
     pub accuracy: f64,
     pub opening_name: Option<String>,
+    #[serde(default)]
+    pub missed_punishments: u32,
+    /// ECO code of the deepest catalogued position reached
+    #[serde(default)]
+    pub eco: Option<String>,
+}
+
+/// Whole-game review written by the coach
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GameReport {
+    pub opening: String,
+    pub middlegame_turning_point: String,
+    pub endgame: String,
+    pub takeaways: Vec<String>,
+    // Style the review was written in, filled in by the backend
+    #[serde(default)]
+    pub style: CoachStyle,
+}
+
+/// Coaching comment for one move of a game
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CoachComment {
+    pub move_index: usize,
+    pub comment: String,
+}
+
+// Coach style models
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    En,
+    El,
+    De,
+    Es,
+}
+
+impl Language {
+    /// Parse a settings value such as "el"; unknown codes fall back to English
+    pub fn from_code(code: &str) -> Self {
+        match code.trim().to_lowercase().as_str() {
+            "el" => Self::El,
+            "de" => Self::De,
+            "es" => Self::Es,
+            _ => Self::En,
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        match self {
+            Self::En => "en",
+            Self::El => "el",
+            Self::De => "de",
+            Self::Es => "es",
+        }
+    }
+
+    /// English name of the language, for LLM prompts
+    pub fn name(&self) -> &str {
+        match self {
+            Self::En => "English",
+            Self::El => "Greek",
+            Self::De => "German",
+            Self::Es => "Spanish",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CoachLevel {
+    Beginner,
+    #[default]
+    Intermediate,
+    Advanced,
+}
+
+impl CoachLevel {
+    /// Parse a settings value such as "beginner"; unknown values fall back to Intermediate
+    pub fn from_code(code: &str) -> Self {
+        match code.trim().to_lowercase().as_str() {
+            "beginner" => Self::Beginner,
+            "advanced" => Self::Advanced,
+            _ => Self::Intermediate,
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        match self {
+            Self::Beginner => "beginner",
+            Self::Intermediate => "intermediate",
+            Self::Advanced => "advanced",
+        }
+    }
+}
+
+/// Language and level the coach writes for, from the `coach_language` and
+/// `coach_level` settings
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub struct CoachStyle {
+    pub language: Language,
+    pub level: CoachLevel,
 }
 
 // Opening models
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Opening {
+    // Stable identifier; derived from the name for version 1 catalog files
+    #[serde(default)]
+    pub id: String,
+    // Family this opening is a variation of
+    #[serde(default)]
+    pub parent: Option<String>,
     pub eco: String,
     pub name: String,
     pub pgn: String,
@@ -198,14 +579,227 @@ pub struct Opening {
     pub category: OpeningCategory,
     pub description: String,
     pub key_ideas: Vec<String>,
+    // Typical plans for both sides
+    #[serde(default)]
+    pub plans: Vec<String>,
+}
+
+/// An opening with its variations, for browsing the catalog as a tree
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OpeningNode {
+    pub opening: Opening,
+    pub children: Vec<OpeningNode>,
+}
+
+/// An opening line from the catalog or the ECO table
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NamedOpening {
+    pub eco: String,
+    pub name: String,
+    pub pgn: String,
+    pub moves: Vec<String>,
+    // Catalog entry with description and ideas, when the line is in the catalog
+    pub details: Option<Opening>,
+}
+
+/// The opening a position belongs to
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IdentifiedOpening {
+    pub opening: NamedOpening,
+    // Whether the line ends in the position rather than before it
+    pub exact: bool,
+}
+
+/// A move prepared in the user's opening repertoire
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RepertoireMove {
+    pub id: i64,
+    pub color: String, // the side whose repertoire this is
+    pub fen: String,
+    pub san: String,
+    pub uci: String,
+    pub fen_after: String,
+}
+
+/// A repertoire position with the moves prepared there
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RepertoireNode {
+    pub fen: String,
+    pub note: Option<String>,
+    pub user_to_move: bool,
+    pub moves: Vec<RepertoireMove>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RepertoireImport {
+    pub moves_added: usize,
+    pub notes_added: usize,
+}
+
+/// Which stored games the opening explorer looks at
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ExplorerFilter {
+    pub username: Option<String>,
+    pub color: Option<String>, // the fetched account's side
+    pub time_class: Option<String>,
+    // Game end times, unix seconds
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+}
+
+/// The most recent game that played an explorer move
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExplorerGame {
+    pub url: String,
+    pub white: String,
+    pub black: String,
+    pub end_time: Option<u64>,
+}
+
+/// A move played from an explorer position, with how it went
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExplorerMove {
+    pub san: String,
+    pub uci: String,
+    pub games: u32,
+    pub white_wins: u32,
+    pub draws: u32,
+    pub black_wins: u32,
+    pub score: f64, // percent for the side that played the move
+    pub last_game: ExplorerGame,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExplorerPosition {
+    pub fen: String,
+    pub games: u32,
+    pub moves: Vec<ExplorerMove>, // most played first
+}
+
+/// How games are grouped for opening statistics
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OpeningGrouping {
+    Eco,    // one row per ECO code
+    Family, // one row per opening name before the variation
 }
 
+/// How the user does in one opening with one color
 #[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OpeningStats {
+    pub key: String, // ECO code or family name
+    pub name: String,
+    pub color: String,
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    pub score: f64, // percent, a draw counts half
+    pub analyzed: u32,
+    // Averages over the games that have them
+    pub avg_accuracy: Option<f64>,
+    pub avg_opponent_rating: Option<f64>,
+    pub avg_book_exit_eval: Option<f64>, // pawns, positive = good for the user
+}
+
+/// Where the lines of a drill come from
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DrillSource {
+    Repertoire,
+    Catalog,
+}
+
+/// How a line has gone in drills so far
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DrillStats {
+    pub moves_played: u32,
+    pub moves_correct: u32,
+    pub completions: u32,
+    pub last_drilled: Option<i64>, // unix seconds
+}
+
+/// An opening line to practice, played from the initial position
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DrillLine {
+    pub id: String,
+    pub source: DrillSource,
+    pub name: String,
+    pub color: String, // the side the user plays
+    pub moves: Vec<String>,
+    #[serde(default)]
+    pub stats: DrillStats,
+}
+
+/// The state of a drill after the app's turn
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DrillStep {
+    pub correct: bool,
+    pub finished: bool,
+    // Position the user moves from next
+    pub fen: String,
+    // The app's answer, already included in `fen`
+    pub reply: Option<String>,
+    pub reply_san: Option<String>,
+    // Prepared moves, after a wrong answer
+    pub expected: Vec<String>,
+    pub expected_uci: Vec<String>,
+}
+
+/// Who left the repertoire first
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum DeviationKind {
+    // The user played a move other than the prepared one
+    UserError,
+    // The opponent played a move the repertoire does not cover
+    OpponentNovelty,
+}
+
+/// The first move of a game that left the user's repertoire
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RepertoireDeviation {
+    pub move_index: usize,
+    pub move_number: u32,
+    pub color: String, // side that played the move
+    pub fen: String,   // position before the move
+    pub expected: Vec<String>, // repertoire moves there, main line first
+    pub played: String,
+    pub kind: DeviationKind,
+}
+
+/// A repertoire position where the user keeps playing the wrong move
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ForgottenLine {
+    pub color: String,
+    pub fen: String,
+    pub line: Vec<String>, // SAN moves leading to the position
+    pub expected: Vec<String>,
+    pub played: String, // most recent wrong move
+    pub times: u32,
+    pub last_game_url: String,
+    pub last_date: String,
+}
+
+/// One line of the bundled ECO table
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EcoOpening {
+    pub eco: String,
+    pub name: String,
+    pub pgn: String,
+    pub moves: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum OpeningCategory {
+    #[serde(alias = "king_pawn")]
     KingPawn,
+    #[serde(alias = "queen_pawn")]
     QueenPawn,
+    #[serde(alias = "flank")]
     Flank,
+    #[serde(alias = "indian")]
     Indian,
+    #[serde(alias = "semi_open")]
     SemiOpen,
 }
 
@@ -218,6 +812,8 @@ pub struct Lesson {
     pub difficulty: Difficulty,
     pub description: String,
     pub content: Vec<LessonSection>,
+    // Progress, not part of the catalog files
+    #[serde(default)]
     pub completed: bool,
 }
 
@@ -243,6 +839,8 @@ pub enum Difficulty {
 pub struct LessonSection {
     pub title: String,
     pub content: String,
+    #[serde(default)]
     pub fen: Option<String>,
+    #[serde(default)]
     pub moves: Option<Vec<String>>,
 }
@@ -1,327 +1,254 @@
+use crate::catalog;
+use crate::eco;
 use crate::models::*;
+use crate::polyglot;
+use shakmaty::Chess;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
 
+/// Bundled openings with the user's additions, checked when loaded
 pub fn get_all_openings() -> Vec<Opening> {
-    vec![
-        // === KING'S PAWN OPENINGS (e4) ===
-        Opening {
-            eco: "C50".to_string(),
-            name: "Italian Game".to_string(),
-            pgn: "1. e4 e5 2. Nf3 Nc6 3. Bc4".to_string(),
-            moves: vec!["e4".into(), "e5".into(), "Nf3".into(), "Nc6".into(), "Bc4".into()],
-            category: OpeningCategory::KingPawn,
-            description: "One of the oldest and most classical openings. White develops the bishop to c4, targeting the f7 pawn and fighting for the center.".to_string(),
-            key_ideas: vec![
-                "Control the center with e4 and d4".into(),
-                "Develop pieces quickly to active squares".into(),
-                "Target the weak f7 pawn".into(),
-                "Prepare for kingside castling".into(),
-            ],
-        },
-        Opening {
-            eco: "C60".to_string(),
-            name: "Ruy Lopez (Spanish Game)".to_string(),
-            pgn: "1. e4 e5 2. Nf3 Nc6 3. Bb5".to_string(),
-            moves: vec!["e4".into(), "e5".into(), "Nf3".into(), "Nc6".into(), "Bb5".into()],
-            category: OpeningCategory::KingPawn,
-            description: "The Ruy Lopez is one of the most popular and deeply analyzed openings. White pins the knight that defends e5, creating long-term pressure.".to_string(),
-            key_ideas: vec![
-                "Put pressure on e5 through the pin on Nc6".into(),
-                "Build a strong pawn center with d4".into(),
-                "Control the game for the long term".into(),
-                "The Marshall Attack is a famous counter-gambit".into(),
-            ],
-        },
-        Opening {
-            eco: "C44".to_string(),
-            name: "Scotch Game".to_string(),
-            pgn: "1. e4 e5 2. Nf3 Nc6 3. d4".to_string(),
-            moves: vec!["e4".into(), "e5".into(), "Nf3".into(), "Nc6".into(), "d4".into()],
-            category: OpeningCategory::KingPawn,
-            description: "White immediately strikes in the center with d4, leading to open positions with active piece play.".to_string(),
-            key_ideas: vec![
-                "Open the center immediately".into(),
-                "Gain space and initiative".into(),
-                "Lead to tactical, open positions".into(),
-                "Kasparov popularized it at the highest level".into(),
-            ],
-        },
-        Opening {
-            eco: "C25".to_string(),
-            name: "Vienna Game".to_string(),
-            pgn: "1. e4 e5 2. Nc3".to_string(),
-            moves: vec!["e4".into(), "e5".into(), "Nc3".into()],
-            category: OpeningCategory::KingPawn,
-            description: "A flexible opening where White develops the knight to c3, keeping options for f4 (Vienna Gambit) or Bc4.".to_string(),
-            key_ideas: vec![
-                "Prepare f4 for aggressive play".into(),
-                "Flexible development".into(),
-                "Can transpose into many lines".into(),
-                "Good surprise weapon".into(),
-            ],
-        },
-        Opening {
-            eco: "C30".to_string(),
-            name: "King's Gambit".to_string(),
-            pgn: "1. e4 e5 2. f4".to_string(),
-            moves: vec!["e4".into(), "e5".into(), "f4".into()],
-            category: OpeningCategory::KingPawn,
-            description: "One of the most romantic and aggressive openings. White sacrifices the f-pawn to gain rapid development and open the f-file.".to_string(),
-            key_ideas: vec![
-                "Sacrifice a pawn for rapid development".into(),
-                "Open the f-file for the rook".into(),
-                "Attack the black king aggressively".into(),
-                "Lead to sharp, tactical positions".into(),
-            ],
-        },
+    catalog::openings().to_vec()
+}
+
+pub fn get_openings_by_category(category: OpeningCategory) -> Vec<Opening> {
+    catalog::openings().iter().filter(|o| o.category == category).cloned().collect()
+}
+
+/// Opening families with their variations nested below them, optionally
+/// limited to one category
+pub fn get_opening_tree(category: Option<OpeningCategory>) -> Vec<OpeningNode> {
+    let openings = catalog::openings();
+    let mut children: HashMap<&str, Vec<&Opening>> = HashMap::new();
+    for o in openings {
+        if let Some(parent) = &o.parent {
+            children.entry(parent.as_str()).or_default().push(o);
+        }
+    }
+    openings
+        .iter()
+        .filter(|o| o.parent.is_none())
+        .filter(|o| category.is_none_or(|c| o.category == c))
+        .map(|o| opening_node(o, &children))
+        .collect()
+}
+
+fn opening_node(opening: &Opening, children: &HashMap<&str, Vec<&Opening>>) -> OpeningNode {
+    OpeningNode {
+        opening: opening.clone(),
+        children: children
+            .get(opening.id.as_str())
+            .map(|c| c.iter().map(|o| opening_node(o, children)).collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// The direct variations of the opening with `id`
+pub fn get_opening_variations(id: &str) -> Vec<Opening> {
+    catalog::openings()
+        .iter()
+        .filter(|o| o.parent.as_deref() == Some(id))
+        .cloned()
+        .collect()
+}
+
+/// A line of the catalog or of the ECO table
+#[derive(Clone, Copy)]
+enum Line {
+    Catalog(usize),
+    Eco(usize),
+}
+
+struct PositionIndex {
+    /// Position keys after each move of every catalog and ECO line
+    catalog_keys: Vec<Vec<u64>>,
+    eco_keys: Vec<Vec<u64>>,
+    /// Every position of every line, to the lines and plies reaching it
+    along: HashMap<u64, Vec<(Line, usize)>>,
+    /// Position at the end of each line; a catalog line wins over the ECO table
+    named: HashMap<u64, Line>,
+}
+
+fn index() -> &'static PositionIndex {
+    static INDEX: OnceLock<PositionIndex> = OnceLock::new();
+    INDEX.get_or_init(|| {
+        let mut index = PositionIndex {
+            catalog_keys: catalog::openings().iter().map(|o| catalog::line_keys(&o.moves)).collect(),
+            eco_keys: eco::all().iter().map(|o| catalog::line_keys(&o.moves)).collect(),
+            along: HashMap::new(),
+            named: HashMap::new(),
+        };
+        let lines = (0..index.catalog_keys.len())
+            .map(Line::Catalog)
+            .chain((0..index.eco_keys.len()).map(Line::Eco))
+            .collect::<Vec<_>>();
+        for line in lines {
+            let keys = match line {
+                Line::Catalog(i) => &index.catalog_keys[i],
+                Line::Eco(i) => &index.eco_keys[i],
+            };
+            for (ply, key) in keys.iter().enumerate() {
+                index.along.entry(*key).or_default().push((line, ply));
+            }
+            if let Some(end) = keys.last() {
+                index.named.entry(*end).or_insert(line);
+            }
+        }
+        index
+    })
+}
+
+fn named_opening(line: Line) -> NamedOpening {
+    match line {
+        Line::Catalog(i) => {
+            let o = &catalog::openings()[i];
+            NamedOpening {
+                eco: o.eco.clone(),
+                name: o.name.clone(),
+                pgn: o.pgn.clone(),
+                moves: o.moves.clone(),
+                details: Some(o.clone()),
+            }
+        }
+        Line::Eco(i) => {
+            let o = &eco::all()[i];
+            NamedOpening {
+                eco: o.eco.clone(),
+                name: o.name.clone(),
+                pgn: o.pgn.clone(),
+                moves: o.moves.clone(),
+                details: None,
+            }
+        }
+    }
+}
 
-        // === SICILIAN DEFENSE ===
-        Opening {
-            eco: "B20".to_string(),
-            name: "Sicilian Defense".to_string(),
-            pgn: "1. e4 c5".to_string(),
-            moves: vec!["e4".into(), "c5".into()],
-            category: OpeningCategory::SemiOpen,
-            description: "The most popular defense against 1.e4. Black fights for the center asymmetrically and aims for counterplay on the queenside.".to_string(),
-            key_ideas: vec![
-                "Create an asymmetric pawn structure".into(),
-                "Counter-attack on the queenside".into(),
-                "Rich tactical and strategic possibilities".into(),
-                "Semi-open c-file for Black's counterplay".into(),
-            ],
-        },
-        Opening {
-            eco: "B33".to_string(),
-            name: "Sicilian Najdorf".to_string(),
-            pgn: "1. e4 c5 2. Nf3 d6 3. d4 cxd4 4. Nxd4 Nf6 5. Nc3 a6".to_string(),
-            moves: vec!["e4".into(), "c5".into(), "Nf3".into(), "d6".into(), "d4".into(), "cxd4".into(), "Nxd4".into(), "Nf6".into(), "Nc3".into(), "a6".into()],
-            category: OpeningCategory::SemiOpen,
-            description: "The sharpest and most popular Sicilian variation. Bobby Fischer and Garry Kasparov were famous practitioners.".to_string(),
-            key_ideas: vec![
-                "Flexible pawn structure for Black".into(),
-                "Prepare ...e5 or ...b5 expansion".into(),
-                "Extremely rich tactical play".into(),
-                "One of the most theoretically complex openings".into(),
-            ],
-        },
-        Opening {
-            eco: "B90".to_string(),
-            name: "Sicilian Dragon".to_string(),
-            pgn: "1. e4 c5 2. Nf3 d6 3. d4 cxd4 4. Nxd4 Nf6 5. Nc3 g6".to_string(),
-            moves: vec!["e4".into(), "c5".into(), "Nf3".into(), "d6".into(), "d4".into(), "cxd4".into(), "Nxd4".into(), "Nf6".into(), "Nc3".into(), "g6".into()],
-            category: OpeningCategory::SemiOpen,
-            description: "Black fianchettoes the bishop on g7, creating a 'dragon' formation. Known for the vicious Yugoslav Attack.".to_string(),
-            key_ideas: vec![
-                "Fianchetto the dark-squared bishop".into(),
-                "Counterplay along the c-file".into(),
-                "Yugoslav Attack leads to opposite-side castling battles".into(),
-                "Dynamic and aggressive for both sides".into(),
-            ],
-        },
+/// Name a position by the deepest catalog or ECO line reaching it. Lines are
+/// matched by position, so any move order counts. A position inside a line
+/// takes the name of the deepest named position on the way to it.
+pub fn identify_opening(pos: &Chess) -> Option<IdentifiedOpening> {
+    let index = index();
+    let key = polyglot::key(pos);
+    if let Some(&line) = index.named.get(&key) {
+        return Some(IdentifiedOpening {
+            opening: named_opening(line),
+            exact: true,
+        });
+    }
+
+    let mut best: Option<(usize, Line)> = None;
+    for &(line, ply) in index.along.get(&key)? {
+        let keys = match line {
+            Line::Catalog(i) => &index.catalog_keys[i],
+            Line::Eco(i) => &index.eco_keys[i],
+        };
+        let found = (0..ply)
+            .rev()
+            .find_map(|p| index.named.get(&keys[p]).map(|&named| (p, named)));
+        if let Some((p, named)) = found {
+            if best.is_none_or(|(b, _)| p > b) {
+                best = Some((p, named));
+            }
+        }
+    }
+    best.map(|(_, line)| IdentifiedOpening {
+        opening: named_opening(line),
+        exact: false,
+    })
+}
 
-        // === FRENCH DEFENSE ===
-        Opening {
-            eco: "C00".to_string(),
-            name: "French Defense".to_string(),
-            pgn: "1. e4 e6".to_string(),
-            moves: vec!["e4".into(), "e6".into()],
-            category: OpeningCategory::SemiOpen,
-            description: "A solid defense where Black prepares ...d5 to challenge White's center. Leads to strategic, complex positions.".to_string(),
-            key_ideas: vec![
-                "Challenge the center with ...d5".into(),
-                "Solid pawn structure".into(),
-                "Counterplay on the queenside".into(),
-                "The light-squared bishop is often a strategic challenge".into(),
-            ],
-        },
+/// The catalog opening with an ECO code, or the code's main line in the ECO table
+pub fn get_opening_by_eco(code: &str) -> Option<NamedOpening> {
+    let code = code.trim().to_uppercase();
+    if let Some(i) = catalog::openings().iter().position(|o| o.eco == code) {
+        return Some(named_opening(Line::Catalog(i)));
+    }
+    eco::all()
+        .iter()
+        .position(|o| o.eco == code)
+        .map(|i| named_opening(Line::Eco(i)))
+}
 
-        // === CARO-KANN ===
-        Opening {
-            eco: "B10".to_string(),
-            name: "Caro-Kann Defense".to_string(),
-            pgn: "1. e4 c6".to_string(),
-            moves: vec!["e4".into(), "c6".into()],
-            category: OpeningCategory::SemiOpen,
-            description: "A solid, positional defense preparing ...d5. Unlike the French, it doesn't block the light-squared bishop.".to_string(),
-            key_ideas: vec![
-                "Solid center with ...d5".into(),
-                "Light-squared bishop remains active".into(),
-                "Less tactical than the Sicilian".into(),
-                "Good for players who prefer solid, strategic play".into(),
-            ],
-        },
+/// Openings whose names match `query`, best first. Every word of the query
+/// must match a word of the name exactly, as a prefix, inside it, or with a
+/// typo or two; catalog openings come before ECO lines of equal score.
+pub fn search_openings(query: &str, limit: usize) -> Vec<NamedOpening> {
+    let query = words(query);
+    if query.is_empty() {
+        return Vec::new();
+    }
 
-        // === PIRC DEFENSE ===
-        Opening {
-            eco: "B07".to_string(),
-            name: "Pirc Defense".to_string(),
-            pgn: "1. e4 d6 2. d4 Nf6 3. Nc3 g6".to_string(),
-            moves: vec!["e4".into(), "d6".into(), "d4".into(), "Nf6".into(), "Nc3".into(), "g6".into()],
-            category: OpeningCategory::SemiOpen,
-            description: "A hypermodern defense where Black allows White to build a center, then attacks it with pieces.".to_string(),
-            key_ideas: vec![
-                "Hypermodern approach to the center".into(),
-                "Fianchetto the king's bishop".into(),
-                "Counter-attack the center later".into(),
-                "Flexible and less theory-heavy".into(),
-            ],
-        },
+    let catalog = catalog::openings().iter().enumerate().map(|(i, o)| (Line::Catalog(i), &o.name));
+    let table = eco::all().iter().enumerate().map(|(i, o)| (Line::Eco(i), &o.name));
+    let mut seen = HashSet::new();
+    let mut found: Vec<(f64, usize, Line)> = catalog
+        .chain(table)
+        .filter(|(_, name)| seen.insert(name.to_lowercase()))
+        .filter_map(|(line, name)| {
+            let score = name_score(&query, &words(name))?;
+            Some((score, name.len(), line))
+        })
+        .collect();
+    // Stable sort keeps catalog lines ahead on ties
+    found.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+    found.into_iter().take(limit).map(|(_, _, line)| named_opening(line)).collect()
+}
 
-        // === SCANDINAVIAN ===
-        Opening {
-            eco: "B01".to_string(),
-            name: "Scandinavian Defense".to_string(),
-            pgn: "1. e4 d5".to_string(),
-            moves: vec!["e4".into(), "d5".into()],
-            category: OpeningCategory::SemiOpen,
-            description: "Black immediately challenges e4 with ...d5. After 2. exd5 Qxd5, the queen comes out early but Black has a solid setup.".to_string(),
-            key_ideas: vec![
-                "Immediate central confrontation".into(),
-                "Simple development scheme".into(),
-                "Queen comes out early to d5".into(),
-                "Solid and practical choice".into(),
-            ],
-        },
+fn words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .replace("defence", "defense")
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_string())
+        .collect()
+}
 
-        // === QUEEN'S PAWN OPENINGS ===
-        Opening {
-            eco: "D30".to_string(),
-            name: "Queen's Gambit".to_string(),
-            pgn: "1. d4 d5 2. c4".to_string(),
-            moves: vec!["d4".into(), "d5".into(), "c4".into()],
-            category: OpeningCategory::QueenPawn,
-            description: "One of the oldest and most respected openings. White offers the c-pawn to gain central control.".to_string(),
-            key_ideas: vec![
-                "Fight for central control".into(),
-                "The pawn sacrifice is temporary".into(),
-                "Two main responses: Accepted and Declined".into(),
-                "Leads to rich strategic play".into(),
-            ],
-        },
-        Opening {
-            eco: "D35".to_string(),
-            name: "Queen's Gambit Declined".to_string(),
-            pgn: "1. d4 d5 2. c4 e6".to_string(),
-            moves: vec!["d4".into(), "d5".into(), "c4".into(), "e6".into()],
-            category: OpeningCategory::QueenPawn,
-            description: "Black declines the gambit and supports the d5 pawn. One of the most classical and solid openings.".to_string(),
-            key_ideas: vec![
-                "Maintain a solid pawn center".into(),
-                "Classical piece development".into(),
-                "The minority attack on the queenside".into(),
-                "Strategically deep positions".into(),
-            ],
-        },
-        Opening {
-            eco: "E60".to_string(),
-            name: "King's Indian Defense".to_string(),
-            pgn: "1. d4 Nf6 2. c4 g6 3. Nc3 Bg7".to_string(),
-            moves: vec!["d4".into(), "Nf6".into(), "c4".into(), "g6".into(), "Nc3".into(), "Bg7".into()],
-            category: OpeningCategory::Indian,
-            description: "A dynamic defense where Black allows White to build a large center, then counter-attacks it. Bobby Fischer's favorite.".to_string(),
-            key_ideas: vec![
-                "Allow White to build a center, then attack it".into(),
-                "Kingside attack with ...f5 and ...g5".into(),
-                "The fianchettoed bishop is very powerful".into(),
-                "Dynamic and aggressive counterplay".into(),
-            ],
-        },
-        Opening {
-            eco: "E00".to_string(),
-            name: "Nimzo-Indian Defense".to_string(),
-            pgn: "1. d4 Nf6 2. c4 e6 3. Nc3 Bb4".to_string(),
-            moves: vec!["d4".into(), "Nf6".into(), "c4".into(), "e6".into(), "Nc3".into(), "Bb4".into()],
-            category: OpeningCategory::Indian,
-            description: "One of the most respected defenses. Black pins the knight on c3, fighting for control of e4.".to_string(),
-            key_ideas: vec![
-                "Pin the knight to control e4".into(),
-                "Excellent piece activity".into(),
-                "Flexible pawn structure".into(),
-                "Considered one of the best replies to 1.d4".into(),
-            ],
-        },
-        Opening {
-            eco: "D70".to_string(),
-            name: "Grünfeld Defense".to_string(),
-            pgn: "1. d4 Nf6 2. c4 g6 3. Nc3 d5".to_string(),
-            moves: vec!["d4".into(), "Nf6".into(), "c4".into(), "g6".into(), "Nc3".into(), "d5".into()],
-            category: OpeningCategory::Indian,
-            description: "A hypermodern defense where Black strikes at the center with ...d5 after fianchettoing. Very dynamic and theoretical.".to_string(),
-            key_ideas: vec![
-                "Strike at the center with ...d5".into(),
-                "Pressure on d4 with the fianchettoed bishop".into(),
-                "Very dynamic pawn play".into(),
-                "Kasparov used it extensively".into(),
-            ],
-        },
-        Opening {
-            eco: "A45".to_string(),
-            name: "London System".to_string(),
-            pgn: "1. d4 d5 2. Bf4".to_string(),
-            moves: vec!["d4".into(), "d5".into(), "Bf4".into()],
-            category: OpeningCategory::QueenPawn,
-            description: "A solid, system-based opening where White develops the bishop to f4 early. Popular at all levels for its simplicity and reliability.".to_string(),
-            key_ideas: vec![
-                "Solid development scheme".into(),
-                "Bishop on f4 controls key squares".into(),
-                "Easy to learn and play".into(),
-                "Avoid heavy theory".into(),
-            ],
-        },
+/// Mean of each query word's best match in the name, if all of them match
+fn name_score(query: &[String], name: &[String]) -> Option<f64> {
+    let mut total = 0.0;
+    for q in query {
+        let best = name.iter().map(|w| word_score(q, w)).fold(0.0, f64::max);
+        if best == 0.0 {
+            return None;
+        }
+        total += best;
+    }
+    Some(total / query.len() as f64)
+}
 
-        // === FLANK OPENINGS ===
-        Opening {
-            eco: "A10".to_string(),
-            name: "English Opening".to_string(),
-            pgn: "1. c4".to_string(),
-            moves: vec!["c4".into()],
-            category: OpeningCategory::Flank,
-            description: "A flexible flank opening that can transpose into many systems. White fights for the center from the side.".to_string(),
-            key_ideas: vec![
-                "Control d5 from the flank".into(),
-                "Very flexible - can transpose to many systems".into(),
-                "Often leads to positional play".into(),
-                "Popular with top-level players".into(),
-            ],
-        },
-        Opening {
-            eco: "A04".to_string(),
-            name: "Réti Opening".to_string(),
-            pgn: "1. Nf3 d5 2. c4".to_string(),
-            moves: vec!["Nf3".into(), "d5".into(), "c4".into()],
-            category: OpeningCategory::Flank,
-            description: "A hypermodern opening where White develops knights before pawns and aims to control the center from afar.".to_string(),
-            key_ideas: vec![
-                "Hypermodern center control".into(),
-                "Flexible pawn structure".into(),
-                "Can transpose to Queen's Gambit lines".into(),
-                "Richard Réti's revolutionary approach".into(),
-            ],
-        },
-        Opening {
-            eco: "A00".to_string(),
-            name: "Bird's Opening".to_string(),
-            pgn: "1. f4".to_string(),
-            moves: vec!["f4".into()],
-            category: OpeningCategory::Flank,
-            description: "An aggressive flank opening controlling the e5 square. Leads to creative, unbalanced positions.".to_string(),
-            key_ideas: vec![
-                "Control e5 with the f-pawn".into(),
-                "Often follows with fianchetto of king's bishop".into(),
-                "Creative and less theoretical".into(),
-                "Can lead to reversed Dutch positions".into(),
-            ],
-        },
-    ]
+fn word_score(query: &str, word: &str) -> f64 {
+    if query == word {
+        1.0
+    } else if word.starts_with(query) {
+        0.9
+    } else if query.len() >= 3 && word.contains(query) {
+        0.7
+    } else {
+        // Allow a typo in longer words, two in long ones
+        let allowed = match query.chars().count() {
+            0..=3 => 0,
+            4..=6 => 1,
+            _ => 2,
+        };
+        if allowed > 0 && edit_distance(query, word) <= allowed {
+            0.6
+        } else {
+            0.0
+        }
+    }
 }
 
-pub fn get_openings_by_category(category: &str) -> Vec<Opening> {
-    let all = get_all_openings();
-    match category {
-        "king_pawn" => all.into_iter().filter(|o| matches!(o.category, OpeningCategory::KingPawn)).collect(),
-        "queen_pawn" => all.into_iter().filter(|o| matches!(o.category, OpeningCategory::QueenPawn)).collect(),
-        "flank" => all.into_iter().filter(|o| matches!(o.category, OpeningCategory::Flank)).collect(),
-        "indian" => all.into_iter().filter(|o| matches!(o.category, OpeningCategory::Indian)).collect(),
-        "semi_open" => all.into_iter().filter(|o| matches!(o.category, OpeningCategory::SemiOpen)).collect(),
-        _ => all,
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { prev } else { prev + 1 };
+            prev = row[j + 1];
+            row[j + 1] = cost.min(row[j] + 1).min(prev + 1);
+        }
     }
+    row[b.len()]
 }
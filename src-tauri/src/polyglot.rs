@@ -0,0 +1,155 @@
+use crate::analysis;
+use crate::models::BookMove;
+use shakmaty::{
+    san::SanPlus,
+    zobrist::{Zobrist64, ZobristHash},
+    CastlingMode, Chess, EnPassantMode, Move, Position, Role, Square,
+};
+
+/// Book weight a move needs by default to count as theory
+pub const DEFAULT_MIN_WEIGHT: u16 = 1;
+
+/// Size of one book entry: key, move, weight and learn fields
+const ENTRY_SIZE: usize = 16;
+
+struct Entry {
+    key: u64,
+    mv: u16,
+    weight: u16,
+}
+
+/// A Polyglot `.bin` opening book held in memory, sorted by position key
+pub struct Book {
+    entries: Vec<Entry>,
+    min_weight: u16,
+}
+
+/// Polyglot key of a position. shakmaty's 64-bit Zobrist hash uses the
+/// Polyglot random numbers, and Polyglot only hashes an en passant square
+/// when a capture there is possible.
+pub fn key(pos: &Chess) -> u64 {
+    pos.zobrist_hash::<Zobrist64>(EnPassantMode::Legal).0
+}
+
+impl Book {
+    /// Load a book file. Moves weighted below `min_weight` are not theory.
+    pub fn open(path: &str, min_weight: u16) -> Result<Book, String> {
+        let data = std::fs::read(path).map_err(|e| format!("Failed to read book {}: {}", path, e))?;
+        if data.len() % ENTRY_SIZE != 0 {
+            return Err(format!("{} is not a Polyglot book", path));
+        }
+
+        let mut entries: Vec<Entry> = data
+            .chunks_exact(ENTRY_SIZE)
+            .map(|c| Entry {
+                key: u64::from_be_bytes(c[0..8].try_into().unwrap()),
+                mv: u16::from_be_bytes([c[8], c[9]]),
+                weight: u16::from_be_bytes([c[10], c[11]]),
+            })
+            .collect();
+        // Books are written sorted, but a stray file should not break lookups
+        if !entries.windows(2).all(|w| w[0].key <= w[1].key) {
+            entries.sort_by_key(|e| e.key);
+        }
+
+        Ok(Book { entries, min_weight })
+    }
+
+    /// Every book move in `pos`, heaviest first
+    pub fn moves(&self, pos: &Chess) -> Vec<BookMove> {
+        let key = key(pos);
+        let start = self.entries.partition_point(|e| e.key < key);
+        let found: Vec<(Move, u16)> = self.entries[start..]
+            .iter()
+            .take_while(|e| e.key == key)
+            .filter_map(|e| decode(pos, e.mv).map(|m| (m, e.weight)))
+            .collect();
+
+        let total: u32 = found.iter().map(|(_, w)| *w as u32).sum();
+        let mut moves: Vec<BookMove> = found
+            .into_iter()
+            .map(|(m, weight)| {
+                let (from, to) = analysis::extract_move_squares(&m);
+                BookMove {
+                    san: SanPlus::from_move(pos.clone(), &m).to_string(),
+                    uci: m.to_uci(CastlingMode::Standard).to_string(),
+                    from,
+                    to,
+                    weight,
+                    share: if total > 0 {
+                        (weight as f64 * 1000.0 / total as f64).round() / 10.0
+                    } else {
+                        0.0
+                    },
+                }
+            })
+            .collect();
+        moves.sort_by_key(|b| std::cmp::Reverse(b.weight));
+        moves
+    }
+
+    /// Whether `m` is a book move in `pos` with at least the minimum weight
+    pub fn contains(&self, pos: &Chess, m: &Move) -> bool {
+        let key = key(pos);
+        let start = self.entries.partition_point(|e| e.key < key);
+        self.entries[start..]
+            .iter()
+            .take_while(|e| e.key == key)
+            .any(|e| e.weight >= self.min_weight && decode(pos, e.mv).as_ref() == Some(m))
+    }
+}
+
+/// Turn a Polyglot move (to file/rank, from file/rank, promotion piece) into
+/// a legal move. Castling is stored as the king capturing its own rook.
+fn decode(pos: &Chess, raw: u16) -> Option<Move> {
+    let square = |bits: u16| Square::new((bits & 0x3f) as u32);
+    let to = square(raw);
+    let from = square(raw >> 6);
+    let promotion = match (raw >> 12) & 0x7 {
+        1 => Some(Role::Knight),
+        2 => Some(Role::Bishop),
+        3 => Some(Role::Rook),
+        4 => Some(Role::Queen),
+        _ => None,
+    };
+
+    pos.legal_moves().into_iter().find(|m| match m {
+        Move::Castle { king, rook } => *king == from && *rook == to,
+        _ => m.from() == Some(from) && m.to() == to && m.promotion() == promotion,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::uci::UciMove;
+
+    /// Key after playing `moves` from the initial position
+    fn key_after(moves: &[&str]) -> u64 {
+        let mut pos = Chess::default();
+        for uci in moves {
+            let m = uci.parse::<UciMove>().unwrap().to_move(&pos).unwrap();
+            pos.play_unchecked(&m);
+        }
+        key(&pos)
+    }
+
+    // Reference keys from the Polyglot book format specification
+    #[test]
+    fn matches_reference_keys() {
+        let cases: [(&[&str], u64); 9] = [
+            (&[], 0x463b96181691fc9c),
+            (&["e2e4"], 0x823c9b50fd114196),
+            (&["e2e4", "d7d5"], 0x0756b94461c50fb0),
+            (&["e2e4", "d7d5", "e4e5"], 0x662fafb965db29d4),
+            (&["e2e4", "d7d5", "e4e5", "f7f5"], 0x22a48b5a8e47ff78),
+            (&["e2e4", "d7d5", "e4e5", "f7f5", "e1e2"], 0x652a607ca3f242c1),
+            (&["e2e4", "d7d5", "e4e5", "f7f5", "e1e2", "e8f7"], 0x00fdd303c946bdd9),
+            (&["a2a4", "b7b5", "h2h4", "b5b4", "c2c4"], 0x3c8123ea7b067637),
+            (&["a2a4", "b7b5", "h2h4", "b5b4", "c2c4", "b4c3", "a1a3"], 0x5c3f9b829b279560),
+        ];
+        for (moves, expected) in cases {
+            assert_eq!(key_after(moves), expected, "after {:?}", moves);
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use crate::analysis;
+use crate::engine::{self, Limits, Line};
+use crate::models::*;
+use crate::see;
+use crate::tactics;
+use shakmaty::{san::SanPlus, uci::UciMove, CastlingMode, Chess, Move, Position};
+
+/// Advantage (centipawns) the solution must reach to count as winning
+const WINNING: i32 = 200;
+/// Most moves the solver has to find in one puzzle
+const MAX_SOLVER_MOVES: usize = 3;
+
+/// Build puzzles from the Mistakes and Blunders of an analyzed game. Only
+/// moves by `color` are used when given. Positions are kept only when the
+/// engine finds exactly one winning move at every step of the solution.
+pub fn extract_puzzles(analysis: &GameAnalysis, color: Option<&str>) -> Vec<Puzzle> {
+    let mut puzzles = Vec::new();
+
+    for (i, mv) in analysis.moves.iter().enumerate() {
+        if !matches!(mv.classification, MoveClassification::Mistake | MoveClassification::Blunder) {
+            continue;
+        }
+        if color.is_some_and(|c| c != mv.color) {
+            continue;
+        }
+        let Ok(pos) = analysis::parse_fen(&mv.fen_before) else {
+            continue;
+        };
+        let Some(played) = SanPlus::from_ascii(mv.san.as_bytes())
+            .ok()
+            .and_then(|s| s.san.to_move(&pos).ok())
+        else {
+            continue;
+        };
+
+        if let Some(mut puzzle) = build_puzzle(&pos, &played) {
+            puzzle.game_url = analysis.game_url.clone();
+            puzzle.move_index = i;
+            puzzle.color = mv.color.clone();
+            puzzle.played_move = mv.san.clone();
+            puzzles.push(puzzle);
+        }
+    }
+
+    puzzles
+}
+
+/// A puzzle from `pos` if it has a unique winning line that `played` missed
+fn build_puzzle(pos: &Chess, played: &Move) -> Option<Puzzle> {
+    let lines = engine::analyse(pos, Limits::DEEP, 2);
+    if !is_unique(&lines) || lines[0].best() == played {
+        return None;
+    }
+
+    let mut solution = vec![lines[0].best().clone()];
+    let mut current = pos.clone();
+    current.play_unchecked(lines[0].best());
+
+    // Follow the line while the solver keeps having a single winning move
+    while solution.len() < MAX_SOLVER_MOVES * 2 - 1 {
+        let Some(reply) = engine::analyse(&current, Limits::QUICK, 1).into_iter().next() else {
+            break;
+        };
+        let mut next = current.clone();
+        next.play_unchecked(reply.best());
+
+        let lines = engine::analyse(&next, Limits::DEEP, 2);
+        if !is_unique(&lines) {
+            break;
+        }
+        solution.push(reply.best().clone());
+        solution.push(lines[0].best().clone());
+        next.play_unchecked(lines[0].best());
+        current = next;
+    }
+
+    let mut themes = Vec::new();
+    let mut replay = pos.clone();
+    let mut solution_san = Vec::new();
+    for (i, m) in solution.iter().enumerate() {
+        if i % 2 == 0 {
+            for motif in tactics::detect_motifs(&replay, m) {
+                if !themes.contains(&motif.kind) {
+                    themes.push(motif.kind);
+                }
+            }
+        }
+        solution_san.push(SanPlus::from_move(replay.clone(), m).to_string());
+        replay.play_unchecked(m);
+    }
+    let mate_in = replay
+        .is_checkmate()
+        .then(|| solution.len().div_ceil(2) as u32);
+
+    Some(Puzzle {
+        id: 0,
+        game_url: String::new(),
+        move_index: 0,
+        fen: analysis::format_fen(pos),
+        color: String::new(),
+        played_move: String::new(),
+        rating: estimate_rating(pos, &solution, mate_in),
+        solution: solution
+            .iter()
+            .map(|m| m.to_uci(CastlingMode::Standard).to_string())
+            .collect(),
+        solution_san,
+        themes,
+        mate_in,
+        attempts: 0,
+        solved: 0,
+    })
+}
+
+/// The best line wins and the second best does not (or only the best mates)
+fn is_unique(lines: &[Line]) -> bool {
+    let Some(best) = lines.first() else {
+        return false;
+    };
+    let best_mates = engine::mate_in(best.score).is_some_and(|n| n > 0);
+    if best.score < WINNING && !best_mates {
+        return false;
+    }
+    match lines.get(1) {
+        None => true,
+        Some(second) => {
+            let second_mates = engine::mate_in(second.score).is_some_and(|n| n > 0);
+            if best_mates {
+                !second_mates
+            } else {
+                second.score < WINNING && best.score - second.score >= WINNING
+            }
+        }
+    }
+}
+
+/// Rough puzzle rating: longer solutions, quiet moves and sacrifices are harder
+fn estimate_rating(pos: &Chess, solution: &[Move], mate_in: Option<u32>) -> i32 {
+    let mut rating = 600;
+    let mut replay = pos.clone();
+
+    for (i, m) in solution.iter().enumerate() {
+        if i % 2 == 0 {
+            rating += 250;
+            let mut after = replay.clone();
+            after.play_unchecked(m);
+            if !m.is_capture() && !after.is_check() {
+                rating += 150;
+            }
+            if see::see(&replay, m) < 0 {
+                rating += 200;
+            }
+        }
+        replay.play_unchecked(m);
+    }
+
+    if mate_in == Some(1) {
+        rating -= 150;
+    }
+    rating.clamp(400, 2800)
+}
+
+/// Check the solver's move at `step` (an even index into the solution) and
+/// return the opponent's answer when the puzzle goes on. Any mating move is
+/// accepted, like on the big puzzle sites.
+pub fn check_move(puzzle: &Puzzle, step: usize, uci: &str) -> Result<PuzzleMoveResult, String> {
+    if step % 2 == 1 || step >= puzzle.solution.len() {
+        return Err(format!("Step {} is not one of the solver's moves", step));
+    }
+
+    let mut pos = analysis::parse_fen(&puzzle.fen)?;
+    for expected in &puzzle.solution[..step] {
+        let m = parse_uci(&pos, expected)?;
+        pos.play_unchecked(&m);
+    }
+
+    let attempt = parse_uci(&pos, uci)?;
+    let expected = parse_uci(&pos, &puzzle.solution[step])?;
+    let mut after = pos.clone();
+    after.play_unchecked(&attempt);
+
+    let correct = attempt == expected || after.is_checkmate();
+    let solved = correct && (step + 1 >= puzzle.solution.len() || after.is_checkmate());
+
+    if !correct {
+        return Ok(PuzzleMoveResult {
+            correct,
+            solved,
+            reply: None,
+            reply_san: None,
+            expected: puzzle.solution_san.get(step).cloned(),
+        });
+    }
+
+    let (reply, reply_san) = if solved {
+        (None, None)
+    } else {
+        (
+            puzzle.solution.get(step + 1).cloned(),
+            puzzle.solution_san.get(step + 1).cloned(),
+        )
+    };
+    Ok(PuzzleMoveResult {
+        correct,
+        solved,
+        reply,
+        reply_san,
+        expected: None,
+    })
+}
+
+fn parse_uci(pos: &Chess, uci: &str) -> Result<Move, String> {
+    uci.parse::<UciMove>()
+        .map_err(|e| format!("Invalid move {}: {}", uci, e))?
+        .to_move(pos)
+        .map_err(|e| format!("Illegal move {}: {}", uci, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(fen: &str, uci: &str) -> (Chess, Move) {
+        let pos = analysis::parse_fen(fen).unwrap();
+        let m = parse_uci(&pos, uci).unwrap();
+        (pos, m)
+    }
+
+    fn puzzle(fen: &str, solution: &[&str]) -> Puzzle {
+        Puzzle {
+            id: 1,
+            game_url: String::new(),
+            move_index: 0,
+            fen: fen.to_string(),
+            color: "white".to_string(),
+            played_move: String::new(),
+            solution: solution.iter().map(|m| m.to_string()).collect(),
+            solution_san: vec!["Ra7".to_string(), "h6".to_string(), "Re8#".to_string()],
+            themes: Vec::new(),
+            mate_in: None,
+            rating: 1000,
+            attempts: 0,
+            solved: 0,
+        }
+    }
+
+    #[test]
+    fn rejects_two_winning_moves() {
+        // Ra8# and Re8# both mate
+        let (pos, played) = position("6k1/5ppp/8/8/8/8/8/R3R1K1 w - - 0 1", "g1f1");
+        assert!(build_puzzle(&pos, &played).is_none());
+    }
+
+    #[test]
+    fn accepts_a_single_forced_line() {
+        let (pos, played) = position("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "g1f1");
+        let puzzle = build_puzzle(&pos, &played).unwrap();
+        assert_eq!(puzzle.solution, vec!["a1a8"]);
+        assert_eq!(puzzle.mate_in, Some(1));
+    }
+
+    #[test]
+    fn rejects_the_move_that_was_played() {
+        let (pos, played) = position("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8");
+        assert!(build_puzzle(&pos, &played).is_none());
+    }
+
+    #[test]
+    fn rating_grows_with_quiet_moves_and_sacrifices() {
+        let (pos, mate) = position("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8");
+        assert_eq!(estimate_rating(&pos, &[mate], Some(1)), 700);
+
+        let (pos, quiet) = position("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a2");
+        assert_eq!(estimate_rating(&pos, &[quiet], None), 1000);
+
+        // Rook takes a defended pawn
+        let (pos, sacrifice) = position("6k1/5ppp/8/8/8/8/8/5RK1 w - - 0 1", "f1f7");
+        assert_eq!(estimate_rating(&pos, &[sacrifice], None), 1050);
+    }
+
+    #[test]
+    fn checks_each_solver_move() {
+        let fen = "6k1/5ppp/8/8/8/8/5PPP/R3R1K1 w - - 0 1";
+        let puzzle = puzzle(fen, &["a1a7", "h7h6", "e1e8"]);
+
+        let first = check_move(&puzzle, 0, "a1a7").unwrap();
+        assert!(first.correct && !first.solved);
+        assert_eq!(first.reply.as_deref(), Some("h7h6"));
+
+        let wrong = check_move(&puzzle, 0, "g1f1").unwrap();
+        assert!(!wrong.correct && !wrong.solved);
+        assert_eq!(wrong.expected.as_deref(), Some("Ra7"));
+
+        let last = check_move(&puzzle, 2, "e1e8").unwrap();
+        assert!(last.correct && last.solved && last.reply.is_none());
+
+        // The opponent's moves are not the solver's to play
+        assert!(check_move(&puzzle, 1, "h7h6").is_err());
+        assert!(check_move(&puzzle, 0, "a1a9").is_err());
+    }
+
+    #[test]
+    fn accepts_any_mate() {
+        let puzzle = puzzle("6k1/5ppp/8/8/8/8/5PPP/R3R1K1 w - - 0 1", &["a1a7", "h7h6", "e1e8"]);
+        let result = check_move(&puzzle, 0, "e1e8").unwrap();
+        assert!(result.correct && result.solved);
+    }
+}
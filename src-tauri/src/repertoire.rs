@@ -0,0 +1,408 @@
+use crate::analysis;
+use crate::models::{DeviationKind, MoveAnalysis, RepertoireDeviation};
+use crate::polyglot;
+use pgn_reader::{BufferedReader, RawComment, RawTag, Skip, Visitor};
+use shakmaty::{san::SanPlus, CastlingMode, Chess, Position};
+use std::collections::{HashMap, HashSet};
+
+/// One move of the repertoire tree: from the position with `key` to `next_key`
+#[derive(Debug, Clone)]
+pub struct Edge {
+    pub key: u64,
+    pub fen: String,
+    pub san: String,
+    pub uci: String,
+    pub next_key: u64,
+    pub fen_after: String,
+}
+
+/// A comment attached to a repertoire position
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub key: u64,
+    pub fen: String,
+    pub text: String,
+}
+
+/// Lines and comments read from a PGN file
+#[derive(Default)]
+pub struct Import {
+    pub edges: Vec<Edge>,
+    pub notes: Vec<Note>,
+}
+
+/// Repertoires are kept separately for each side
+pub fn check_color(color: &str) -> Result<(), String> {
+    match color {
+        "white" | "black" => Ok(()),
+        _ => Err(format!("Unknown repertoire color: {}", color)),
+    }
+}
+
+fn edge(pos: &Chess, san: &SanPlus) -> Result<(Edge, Chess), String> {
+    let m = san
+        .san
+        .to_move(pos)
+        .map_err(|e| format!("Illegal move {}: {}", san, e))?;
+    let mut after = pos.clone();
+    after.play_unchecked(&m);
+    let edge = Edge {
+        key: polyglot::key(pos),
+        fen: analysis::format_fen(pos),
+        san: SanPlus::from_move(pos.clone(), &m).to_string(),
+        uci: m.to_uci(CastlingMode::Standard).to_string(),
+        next_key: polyglot::key(&after),
+        fen_after: analysis::format_fen(&after),
+    };
+    Ok((edge, after))
+}
+
+/// The moves of a line played from the initial position, given in SAN
+pub fn line_edges(moves: &[String]) -> Result<Vec<Edge>, String> {
+    let mut pos = Chess::default();
+    let mut edges = Vec::with_capacity(moves.len());
+    for text in moves {
+        let san = SanPlus::from_ascii(text.as_bytes()).map_err(|e| format!("Invalid move {}: {}", text, e))?;
+        let (e, after) = edge(&pos, &san)?;
+        edges.push(e);
+        pos = after;
+    }
+    Ok(edges)
+}
+
+struct Importer {
+    pos: Chess,
+    // Position before the last move, where a variation branches off
+    before: Chess,
+    stack: Vec<(Chess, Chess)>,
+    import: Import,
+    error: Option<String>,
+}
+
+impl Visitor for Importer {
+    type Result = ();
+
+    fn begin_game(&mut self) {
+        self.pos = Chess::default();
+        self.before = Chess::default();
+        self.stack.clear();
+    }
+
+    fn tag(&mut self, name: &[u8], _value: RawTag<'_>) {
+        if name == b"FEN" && self.error.is_none() {
+            self.error = Some("Games from a set-up position cannot be imported".to_string());
+        }
+    }
+
+    fn san(&mut self, san_plus: pgn_reader::SanPlus) {
+        if self.error.is_some() {
+            return;
+        }
+        // pgn-reader builds on its own shakmaty version, so go through the text
+        let text = san_plus.to_string();
+        let parsed = SanPlus::from_ascii(text.as_bytes()).map_err(|e| format!("Invalid move {}: {}", text, e));
+        match parsed.and_then(|san| edge(&self.pos, &san)) {
+            Ok((e, after)) => {
+                self.import.edges.push(e);
+                self.before = std::mem::replace(&mut self.pos, after);
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    fn comment(&mut self, comment: RawComment<'_>) {
+        let text = String::from_utf8_lossy(comment.as_bytes()).trim().to_string();
+        // Clock and eval annotations are not notes
+        if text.is_empty() || text.starts_with("[%") {
+            return;
+        }
+        self.import.notes.push(Note {
+            key: polyglot::key(&self.pos),
+            fen: analysis::format_fen(&self.pos),
+            text,
+        });
+    }
+
+    fn begin_variation(&mut self) -> Skip {
+        self.stack.push((self.pos.clone(), self.before.clone()));
+        self.pos = self.before.clone();
+        Skip(false)
+    }
+
+    fn end_variation(&mut self) {
+        if let Some((pos, before)) = self.stack.pop() {
+            self.pos = pos;
+            self.before = before;
+        }
+    }
+
+    fn end_game(&mut self) {}
+}
+
+/// Read every game of a PGN file, variations and comments included
+pub fn import_pgn(pgn: &str) -> Result<Import, String> {
+    let mut importer = Importer {
+        pos: Chess::default(),
+        before: Chess::default(),
+        stack: Vec::new(),
+        import: Import::default(),
+        error: None,
+    };
+    BufferedReader::new_cursor(pgn.as_bytes())
+        .read_all(&mut importer)
+        .map_err(|e| format!("Failed to read PGN: {}", e))?;
+
+    match importer.error {
+        Some(e) => Err(e),
+        None => Ok(importer.import),
+    }
+}
+
+/// Write a repertoire as one PGN game. The first move stored at each
+/// position is the main line and the others become variations.
+pub fn export_pgn(color: &str, edges: &[Edge], notes: &HashMap<u64, String>) -> String {
+    let mut tree: HashMap<u64, Vec<&Edge>> = HashMap::new();
+    for e in edges {
+        tree.entry(e.key).or_default().push(e);
+    }
+
+    let root = polyglot::key(&Chess::default());
+    let mut tokens = Vec::new();
+    if let Some(note) = notes.get(&root) {
+        tokens.push(comment(note));
+    }
+    let mut path = HashSet::from([root]);
+    write_moves(&mut tokens, &tree, notes, root, 0, false, &mut path);
+
+    let side = if color == "white" { "White" } else { "Black" };
+    format!(
+        "[Event \"{} repertoire\"]\n[Site \"Chess Coach\"]\n[White \"?\"]\n[Black \"?\"]\n[Result \"*\"]\n\n{} *\n",
+        side,
+        tokens.join(" ").replace("( ", "(").replace(" )", ")")
+    )
+}
+
+fn write_moves(
+    tokens: &mut Vec<String>,
+    tree: &HashMap<u64, Vec<&Edge>>,
+    notes: &HashMap<u64, String>,
+    key: u64,
+    ply: usize,
+    force_number: bool,
+    path: &mut HashSet<u64>,
+) {
+    // Skip moves that repeat a position already on the line
+    let children: Vec<&Edge> = tree
+        .get(&key)
+        .map(|c| c.iter().filter(|e| !path.contains(&e.next_key)).copied().collect())
+        .unwrap_or_default();
+    let Some(main) = children.first() else {
+        return;
+    };
+
+    let write_move = |tokens: &mut Vec<String>, e: &Edge, force: bool| {
+        tokens.push(move_text(&e.san, ply, force));
+        if let Some(note) = notes.get(&e.next_key) {
+            tokens.push(comment(note));
+        }
+    };
+
+    write_move(tokens, main, force_number);
+    for alt in &children[1..] {
+        tokens.push("(".to_string());
+        write_move(tokens, alt, true);
+        path.insert(alt.next_key);
+        let force = notes.contains_key(&alt.next_key);
+        write_moves(tokens, tree, notes, alt.next_key, ply + 1, force, path);
+        path.remove(&alt.next_key);
+        tokens.push(")".to_string());
+    }
+
+    path.insert(main.next_key);
+    // Black's move needs its number again after a variation or a comment
+    let force = children.len() > 1 || notes.contains_key(&main.next_key);
+    write_moves(tokens, tree, notes, main.next_key, ply + 1, force, path);
+    path.remove(&main.next_key);
+}
+
+/// A PGN comment; braces would end it early
+fn comment(note: &str) -> String {
+    format!("{{{}}}", note.replace(['{', '}'], ""))
+}
+
+fn move_text(san: &str, ply: usize, force_number: bool) -> String {
+    let number = ply / 2 + 1;
+    if ply.is_multiple_of(2) {
+        format!("{}. {}", number, san)
+    } else if force_number {
+        format!("{}... {}", number, san)
+    } else {
+        san.to_string()
+    }
+}
+
+/// Whether the repertoire's owner is to move in `pos`
+pub fn user_to_move(color: &str, pos: &Chess) -> bool {
+    (color == "white") == pos.turn().is_white()
+}
+
+/// The first move of a game that leaves the `color` repertoire. A game that
+/// runs past the end of a prepared line has not deviated, and positions are
+/// matched by key so a transposition back into the repertoire still counts.
+pub fn find_deviation(color: &str, edges: &[Edge], moves: &[MoveAnalysis]) -> Option<RepertoireDeviation> {
+    let mut tree: HashMap<u64, Vec<&Edge>> = HashMap::new();
+    for e in edges {
+        tree.entry(e.key).or_default().push(e);
+    }
+
+    for (i, m) in moves.iter().enumerate() {
+        let before = analysis::parse_fen(&m.fen_before).ok()?;
+        let after = analysis::parse_fen(&m.fen_after).ok()?;
+        let prepared = tree.get(&polyglot::key(&before))?;
+        let next_key = polyglot::key(&after);
+        if prepared.iter().any(|e| e.next_key == next_key) {
+            continue;
+        }
+
+        let kind = if user_to_move(color, &before) {
+            DeviationKind::UserError
+        } else {
+            DeviationKind::OpponentNovelty
+        };
+        return Some(RepertoireDeviation {
+            move_index: i,
+            move_number: m.move_number,
+            color: m.color.clone(),
+            fen: m.fen_before.clone(),
+            expected: prepared.iter().map(|e| e.san.clone()).collect(),
+            played: m.san.clone(),
+            kind,
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::MoveClassification;
+
+    const PGN: &str = "1. e4 e5 (1... c5 {Sicilian} 2. Nf3) 2. Nf3 {main line} Nc6 *";
+
+    fn line(moves: &str) -> Vec<String> {
+        moves.split_whitespace().map(|m| m.to_string()).collect()
+    }
+
+    fn sans(edges: &[Edge]) -> Vec<&str> {
+        edges.iter().map(|e| e.san.as_str()).collect()
+    }
+
+    fn notes(import: &Import) -> HashMap<u64, String> {
+        import.notes.iter().map(|n| (n.key, n.text.clone())).collect()
+    }
+
+    /// A game as the analysis would store it
+    fn game(moves: &str) -> Vec<MoveAnalysis> {
+        line_edges(&line(moves))
+            .unwrap()
+            .into_iter()
+            .enumerate()
+            .map(|(i, e)| MoveAnalysis {
+                move_number: i as u32 / 2 + 1,
+                san: e.san,
+                color: if i % 2 == 0 { "white" } else { "black" }.to_string(),
+                classification: MoveClassification::Good,
+                comment: None,
+                is_book_move: false,
+                fen_after: e.fen_after,
+                played_from: None,
+                played_to: None,
+                best_move_san: None,
+                best_from: None,
+                best_to: None,
+                fen_before: e.fen,
+                eval_score: 0.0,
+                motifs: Vec::new(),
+                missed_win: None,
+                threats_before: Vec::new(),
+                candidates: Vec::new(),
+                book_alternatives: Vec::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn imports_variations_and_comments() {
+        let import = import_pgn(PGN).unwrap();
+        assert_eq!(sans(&import.edges), vec!["e4", "e5", "c5", "Nf3", "Nf3", "Nc6"]);
+
+        // The variation branches off the position after 1. e4
+        let after_e4 = import.edges[0].next_key;
+        assert_eq!(import.edges[2].key, after_e4);
+
+        let notes = notes(&import);
+        assert_eq!(notes.get(&import.edges[3].key).map(String::as_str), Some("Sicilian"));
+        assert_eq!(notes.get(&import.edges[5].key).map(String::as_str), Some("main line"));
+    }
+
+    #[test]
+    fn skips_clock_annotations() {
+        let import = import_pgn("1. e4 {[%clk 0:05:00]} e5 *").unwrap();
+        assert!(import.notes.is_empty());
+    }
+
+    #[test]
+    fn rejects_illegal_moves_and_setups() {
+        assert!(import_pgn("1. e4 e4 *").is_err());
+        assert!(import_pgn("[FEN \"4k3/8/8/8/8/8/8/4K3 w - - 0 1\"]\n\n1. Kd2 *").is_err());
+    }
+
+    #[test]
+    fn exports_variations_and_comments() {
+        let import = import_pgn(PGN).unwrap();
+        let pgn = export_pgn("black", &import.edges, &notes(&import));
+        assert!(pgn.starts_with("[Event \"Black repertoire\"]"));
+        assert!(pgn.ends_with("\n\n1. e4 e5 (1... c5 {Sicilian} 2. Nf3) 2. Nf3 {main line} 2... Nc6 *\n"));
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let import = import_pgn(PGN).unwrap();
+        let again = import_pgn(&export_pgn("white", &import.edges, &notes(&import))).unwrap();
+
+        let moves = |i: &Import| -> HashSet<(u64, String)> {
+            i.edges.iter().map(|e| (e.key, e.uci.clone())).collect()
+        };
+        assert_eq!(moves(&again), moves(&import));
+        assert_eq!(notes(&again), notes(&import));
+    }
+
+    #[test]
+    fn white_deviations() {
+        let edges = line_edges(&line("e4 e5 Nf3")).unwrap();
+
+        let own = find_deviation("white", &edges, &game("e4 e5 Bc4")).unwrap();
+        assert_eq!((own.move_index, own.kind), (2, DeviationKind::UserError));
+        assert_eq!(own.expected, vec!["Nf3"]);
+
+        let novelty = find_deviation("white", &edges, &game("e4 c5")).unwrap();
+        assert_eq!((novelty.move_index, novelty.kind), (1, DeviationKind::OpponentNovelty));
+
+        // Running past the end of the line is not a deviation
+        assert!(find_deviation("white", &edges, &game("e4 e5 Nf3 Nc6 Bb5")).is_none());
+    }
+
+    #[test]
+    fn black_deviations() {
+        let edges = line_edges(&line("e4 c5 Nf3 d6")).unwrap();
+
+        let own = find_deviation("black", &edges, &game("e4 c5 Nf3 Nc6")).unwrap();
+        assert_eq!((own.move_index, own.kind), (3, DeviationKind::UserError));
+        assert_eq!(own.played, "Nc6");
+
+        let novelty = find_deviation("black", &edges, &game("d4")).unwrap();
+        assert_eq!((novelty.move_index, novelty.kind), (0, DeviationKind::OpponentNovelty));
+
+        assert!(find_deviation("black", &edges, &game("e4 c5 Nf3 d6 d4")).is_none());
+    }
+}
@@ -0,0 +1,41 @@
+use crate::models::{ReviewGrade, ReviewItem};
+
+/// Ease factor every item starts with
+pub const INITIAL_EASE: f64 = 2.5;
+const MIN_EASE: f64 = 1.3;
+const DAY: i64 = 24 * 60 * 60;
+/// A forgotten item comes back in the same session
+const RELEARN_DELAY: i64 = 10 * 60;
+
+/// Reschedule an item after a review, SM-2 style. `now` is a unix timestamp.
+pub fn schedule(item: &ReviewItem, grade: ReviewGrade, now: i64) -> ReviewItem {
+    let q = grade.quality();
+    let mut next = item.clone();
+
+    // SM-2 ease update, applied for every grade
+    let miss = 5.0 - q as f64;
+    next.ease = (item.ease + 0.1 - miss * (0.08 + miss * 0.02)).max(MIN_EASE);
+
+    if grade == ReviewGrade::Again {
+        next.repetitions = 0;
+        next.lapses += 1;
+        next.interval_days = 0;
+        next.due_at = now + RELEARN_DELAY;
+        return next;
+    }
+
+    next.repetitions += 1;
+    next.interval_days = match next.repetitions {
+        1 => 1,
+        2 => 6,
+        _ => (item.interval_days.max(1) as f64 * next.ease).round() as u32,
+    };
+    match grade {
+        ReviewGrade::Hard => next.interval_days = (item.interval_days.max(1) as f64 * 1.2).round() as u32,
+        ReviewGrade::Easy => next.interval_days = (next.interval_days as f64 * 1.3).round() as u32,
+        _ => {}
+    }
+    next.interval_days = next.interval_days.max(1);
+    next.due_at = now + next.interval_days as i64 * DAY;
+    next
+}
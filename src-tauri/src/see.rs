@@ -0,0 +1,178 @@
+use crate::analysis;
+use crate::models::HangingPiece;
+use crate::tactics::piece_value;
+use shakmaty::{Bitboard, Board, Chess, Color, Move, Position, Role, Square};
+
+/// Static exchange evaluation of a capture (or quiet move) in centipawns,
+/// from the mover's point of view. Both sides keep recapturing on the target
+/// square with their least valuable attacker while it pays off. X-rays are
+/// handled by recomputing attacks as pieces leave the board; pins are not.
+pub fn see(pos: &Chess, m: &Move) -> i32 {
+    let (from, to) = match m {
+        Move::Normal { from, to, .. } | Move::EnPassant { from, to } => (*from, *to),
+        _ => return 0,
+    };
+    let board = pos.board();
+    let mut occupied = board.occupied().without(from);
+    if let Move::EnPassant { from, to } = m {
+        occupied.discard(Square::from_coords(to.file(), from.rank()));
+    }
+
+    let mut first = m.capture().map(piece_value).unwrap_or(0);
+    let mut on_square = m.role();
+    if let Some(promotion) = m.promotion() {
+        first += piece_value(promotion) - piece_value(Role::Pawn);
+        on_square = promotion;
+    }
+
+    first - exchange(board, to, on_square, !pos.turn(), occupied).max(0)
+}
+
+/// Material the side `attacker` can win by capturing on `target` first,
+/// choosing its least valuable attacker (0 if it should not capture)
+pub fn see_square(board: &Board, target: Square, attacker: Color) -> i32 {
+    let Some(role) = board.role_at(target) else {
+        return 0;
+    };
+    if role == Role::King {
+        return 0;
+    }
+    exchange(board, target, role, attacker, board.occupied()).max(0)
+}
+
+/// Best net gain for `side` when it may start capturing the `victim` standing
+/// on `target`. Standard swap-list algorithm.
+fn exchange(board: &Board, target: Square, victim: Role, side: Color, mut occupied: Bitboard) -> i32 {
+    let mut gain = [0i32; 32];
+    let mut depth = 0;
+    let mut on_square = victim;
+    let mut side = side;
+
+    while let Some((sq, role)) = least_valuable_attacker(board, target, side, occupied) {
+        // Kings can only recapture when the square is no longer defended
+        let remaining = occupied.without(sq);
+        if role == Role::King && (board.attacks_to(target, !side, remaining) & remaining).any() {
+            break;
+        }
+        gain[depth] = piece_value(on_square) - if depth > 0 { gain[depth - 1] } else { 0 };
+        depth += 1;
+        occupied.discard(sq);
+        on_square = role;
+        side = !side;
+        if depth == gain.len() {
+            break;
+        }
+    }
+
+    // Either side may stop capturing whenever continuing loses material
+    while depth > 1 {
+        depth -= 1;
+        gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+    }
+    if depth == 0 {
+        0
+    } else {
+        gain[0]
+    }
+}
+
+fn least_valuable_attacker(board: &Board, target: Square, side: Color, occupied: Bitboard) -> Option<(Square, Role)> {
+    let attackers = board.attacks_to(target, side, occupied) & occupied;
+    [Role::Pawn, Role::Knight, Role::Bishop, Role::Rook, Role::Queen, Role::King]
+        .into_iter()
+        .find_map(|role| {
+            (attackers & board.by_role(role))
+                .first()
+                .map(|sq| (sq, role))
+        })
+}
+
+/// Pieces of `color` the opponent can win material against by capturing
+pub fn hanging_pieces(board: &Board, color: Color) -> Vec<HangingPiece> {
+    let mut hanging: Vec<HangingPiece> = (board.by_color(color) & !board.kings())
+        .into_iter()
+        .filter_map(|sq| {
+            let loss = see_square(board, sq, !color);
+            let role = board.role_at(sq)?;
+            (loss > 0).then(|| HangingPiece {
+                square: sq.to_string(),
+                role: format!("{:?}", role),
+                color: if color == Color::White { "white" } else { "black" }.to_string(),
+                loss,
+            })
+        })
+        .collect();
+    hanging.sort_by_key(|h| std::cmp::Reverse(h.loss));
+    hanging
+}
+
+/// Hanging pieces of both sides in a FEN, worst first
+pub fn hanging_pieces_in_fen(fen: &str) -> Result<Vec<HangingPiece>, String> {
+    let pos = analysis::parse_fen(fen)?;
+    let mut hanging = hanging_pieces(pos.board(), Color::White);
+    hanging.extend(hanging_pieces(pos.board(), Color::Black));
+    hanging.sort_by_key(|h| std::cmp::Reverse(h.loss));
+    Ok(hanging)
+}
+
+/// The most material `color` stands to lose on the board right now
+pub fn worst_hanging_loss(board: &Board, color: Color) -> i32 {
+    (board.by_color(color) & !board.kings())
+        .into_iter()
+        .map(|sq| see_square(board, sq, !color))
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::uci::UciMove;
+
+    fn see_of(fen: &str, uci: &str) -> i32 {
+        let pos = analysis::parse_fen(fen).unwrap();
+        let m = uci.parse::<UciMove>().unwrap().to_move(&pos).unwrap();
+        see(&pos, &m)
+    }
+
+    #[test]
+    fn undefended_capture() {
+        assert_eq!(see_of("4k3/8/8/3p4/8/8/8/3RK3 w - - 0 1", "d1d5"), 100);
+    }
+
+    #[test]
+    fn defended_capture() {
+        assert_eq!(see_of("4k3/8/4p3/3p4/8/8/8/3RK3 w - - 0 1", "d1d5"), -400);
+    }
+
+    #[test]
+    fn xray_recapture() {
+        // The rook behind recaptures once the front rook has gone
+        assert_eq!(see_of("3rk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5"), 100);
+        assert_eq!(see_of("3rk3/8/8/3p4/8/8/3R4/4K3 w - - 0 1", "d2d5"), -400);
+    }
+
+    #[test]
+    fn king_cannot_take_a_defended_piece() {
+        assert_eq!(see_of("8/8/8/3pk3/8/8/3R4/3RK3 w - - 0 1", "d2d5"), 100);
+        assert_eq!(see_of("8/8/8/3pk3/8/8/3R4/4K3 w - - 0 1", "d2d5"), -400);
+    }
+
+    #[test]
+    fn en_passant() {
+        assert_eq!(see_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 100);
+        assert_eq!(see_of("4k3/2p5/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 0);
+    }
+
+    #[test]
+    fn hanging_piece() {
+        let pos = analysis::parse_fen("4k3/8/8/3n4/8/8/8/3RK3 w - - 0 1").unwrap();
+        let hanging = hanging_pieces(pos.board(), Color::Black);
+        assert_eq!(hanging.len(), 1);
+        assert_eq!((hanging[0].square.as_str(), hanging[0].loss), ("d5", 320));
+
+        // Defended by a pawn, so the rook should not take it
+        let pos = analysis::parse_fen("4k3/8/4p3/3n4/8/8/8/3RK3 w - - 0 1").unwrap();
+        assert!(hanging_pieces(pos.board(), Color::Black).is_empty());
+    }
+}
@@ -0,0 +1,121 @@
+use crate::analysis;
+use crate::eco;
+use crate::models::{ChessComGame, GameAnalysis, OpeningGrouping, OpeningStats};
+use std::collections::HashMap;
+
+#[derive(Default)]
+struct Totals {
+    name: String,
+    games: u32,
+    wins: u32,
+    draws: u32,
+    losses: u32,
+    analyzed: u32,
+    accuracy: Vec<f64>,
+    opponent_rating: Vec<f64>,
+    book_exit_eval: Vec<f64>,
+}
+
+/// Group the user's games by opening and color. Games whose moves never
+/// reach a catalogued position are left out.
+pub fn opening_stats(
+    username: &str,
+    games: &[(ChessComGame, Option<GameAnalysis>)],
+    grouping: OpeningGrouping,
+) -> Vec<OpeningStats> {
+    let mut groups: HashMap<(String, &str), Totals> = HashMap::new();
+
+    for (game, analysis) in games {
+        let (color, user, opponent) = if game.white.username.eq_ignore_ascii_case(username) {
+            ("white", &game.white, &game.black)
+        } else if game.black.username.eq_ignore_ascii_case(username) {
+            ("black", &game.black, &game.white)
+        } else {
+            continue;
+        };
+        let Some(pgn) = &game.pgn else {
+            continue;
+        };
+        let Some(opening) = eco::classify(&analysis::pgn_san_moves(pgn)).opening else {
+            continue;
+        };
+        let (key, name) = match grouping {
+            OpeningGrouping::Eco => (opening.eco.clone(), eco_name(&opening.eco)),
+            OpeningGrouping::Family => {
+                let family = family(&opening.name).to_string();
+                (family.clone(), family)
+            }
+        };
+
+        let totals = groups.entry((key, color)).or_default();
+        totals.name = name;
+        totals.games += 1;
+        if user.result == "win" {
+            totals.wins += 1;
+        } else if opponent.result == "win" {
+            totals.losses += 1;
+        } else {
+            totals.draws += 1;
+        }
+        if let Some(rating) = opponent.rating {
+            totals.opponent_rating.push(rating as f64);
+        }
+
+        if let Some(a) = analysis {
+            totals.analyzed += 1;
+            totals.accuracy.push(analysis::side_accuracy(&a.moves, color));
+            if let Some(eval) = book_exit_eval(a, color) {
+                totals.book_exit_eval.push(eval);
+            }
+        }
+    }
+
+    let mut stats: Vec<OpeningStats> = groups
+        .into_iter()
+        .map(|((key, color), t)| OpeningStats {
+            key,
+            name: t.name,
+            color: color.to_string(),
+            games: t.games,
+            wins: t.wins,
+            draws: t.draws,
+            losses: t.losses,
+            score: round1((t.wins as f64 + t.draws as f64 / 2.0) / t.games as f64 * 100.0),
+            analyzed: t.analyzed,
+            avg_accuracy: average(&t.accuracy).map(round1),
+            avg_opponent_rating: average(&t.opponent_rating).map(|r| r.round()),
+            avg_book_exit_eval: average(&t.book_exit_eval).map(|e| (e * 100.0).round() / 100.0),
+        })
+        .collect();
+    stats.sort_by(|a, b| b.games.cmp(&a.games).then_with(|| a.key.cmp(&b.key)));
+    stats
+}
+
+/// "Sicilian Defense: Najdorf Variation" belongs to "Sicilian Defense"
+fn family(name: &str) -> &str {
+    name.split(':').next().unwrap_or(name).trim()
+}
+
+/// The table's first name for a code, which is its main line
+fn eco_name(code: &str) -> String {
+    eco::all()
+        .iter()
+        .find(|o| o.eco == code)
+        .map(|o| o.name.clone())
+        .unwrap_or_default()
+}
+
+/// Evaluation after the last book move, from the user's side
+fn book_exit_eval(analysis: &GameAnalysis, color: &str) -> Option<f64> {
+    let last_book = analysis.moves.iter().take_while(|m| m.is_book_move).last()?;
+    let sign = if color == "white" { 1.0 } else { -1.0 };
+    Some(sign * last_book.eval_score)
+}
+
+fn average(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+fn round1(x: f64) -> f64 {
+    (x * 10.0).round() / 10.0
+}
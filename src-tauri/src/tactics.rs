@@ -0,0 +1,370 @@
+use crate::models::{Motif, MotifKind};
+use shakmaty::{attacks, Bitboard, Board, Chess, Color, Move, Piece, Position, Role, Square};
+
+/// Detect the tactical motifs a move creates for the side that plays it.
+/// Overloaded defenders, back-rank weaknesses and trapped pieces that were
+/// already there before the move are left out, and so are forks, pins and
+/// skewers by a move that mates.
+pub fn detect_motifs(pos: &Chess, m: &Move) -> Vec<Motif> {
+    let us = pos.turn();
+    let mut after = pos.clone();
+    after.play_unchecked(m);
+
+    let mut motifs = Vec::new();
+    let board = after.board();
+    let to = m.to();
+    let role = m.promotion().unwrap_or(m.role());
+
+    // A mating move is its own story; the pieces it also hits do not matter
+    if !m.is_castle() && !after.is_checkmate() {
+        // Fork by the moved piece
+        let targets = fork_targets(board, to, Piece { color: us, role });
+        if targets.len() >= 2 && is_safe(board, to, role, us) {
+            motifs.push(motif(MotifKind::Fork, to, &targets));
+        }
+
+        // Pins and skewers along the moved piece's lines
+        motifs.extend(line_motifs(board, to, Piece { color: us, role }));
+    }
+
+    motifs.extend(discoveries(pos.board(), board, m, us));
+
+    // Features of the whole position count only when the move created them
+    let mut before = overloaded_defenders(pos.board(), us);
+    before.extend(back_rank_weakness(pos.board(), !us));
+    if let Ok(passed) = pos.clone().swap_turn() {
+        before.extend(trapped_pieces(&passed, us));
+    }
+    let mut created = overloaded_defenders(board, us);
+    created.extend(back_rank_weakness(board, !us));
+    created.extend(trapped_pieces(&after, us));
+    motifs.extend(
+        created
+            .into_iter()
+            .filter(|c| !before.iter().any(|b| b.kind == c.kind && b.square == c.square)),
+    );
+    motifs
+}
+
+pub fn piece_value(role: Role) -> i32 {
+    match role {
+        Role::Pawn => 100,
+        Role::Knight => 320,
+        Role::Bishop => 330,
+        Role::Rook => 500,
+        Role::Queen => 900,
+        Role::King => 0,
+    }
+}
+
+/// Enemy pieces a piece standing on `sq` attacks that are worth forking:
+/// the king, anything more valuable than the attacker, or anything undefended
+pub fn fork_targets(board: &Board, sq: Square, piece: Piece) -> Vec<Square> {
+    let them = !piece.color;
+    let occupied = board.occupied();
+    let attacked = attacks::attacks(sq, piece, occupied) & board.by_color(them);
+
+    attacked
+        .into_iter()
+        .filter(|&t| match board.role_at(t) {
+            Some(Role::King) => true,
+            Some(r) => {
+                piece_value(r) > piece_value(piece.role) || board.attacks_to(t, them, occupied).is_empty()
+            }
+            None => false,
+        })
+        .collect()
+}
+
+/// A piece on `sq` cannot be won: either nothing attacks it, or it is defended
+/// and every attacker is worth more than it. A king cannot take a defended piece.
+pub fn is_safe(board: &Board, sq: Square, role: Role, owner: Color) -> bool {
+    let occupied = board.occupied();
+    let attackers = board.attacks_to(sq, !owner, occupied);
+    if attackers.is_empty() {
+        return true;
+    }
+    let defended = board.attacks_to(sq, owner, occupied).any();
+    defended
+        && attackers.into_iter().all(|a| match board.role_at(a) {
+            Some(Role::King) | None => true,
+            Some(r) => piece_value(r) > piece_value(role),
+        })
+}
+
+fn motif(kind: MotifKind, square: Square, targets: &[Square]) -> Motif {
+    Motif {
+        kind,
+        square: square.to_string(),
+        targets: targets.iter().map(|t| t.to_string()).collect(),
+    }
+}
+
+/// Pins (absolute and relative) and skewers by a slider on `sq`
+fn line_motifs(board: &Board, sq: Square, piece: Piece) -> Vec<Motif> {
+    let mut motifs = Vec::new();
+    if !matches!(piece.role, Role::Bishop | Role::Rook | Role::Queen) {
+        return motifs;
+    }
+
+    let them = board.by_color(!piece.color);
+    let occupied = board.occupied();
+
+    for front in attacks::attacks(sq, piece, occupied) & them {
+        // X-ray through the first piece to the one behind it on the same line
+        let xray = attacks::attacks(sq, piece, occupied.without(front)) & them;
+        let Some(back) = xray
+            .into_iter()
+            .find(|&b| attacks::between(sq, b).contains(front))
+        else {
+            continue;
+        };
+
+        let (Some(front_role), Some(back_role)) = (board.role_at(front), board.role_at(back)) else {
+            continue;
+        };
+
+        let kind = if back_role == Role::King {
+            MotifKind::AbsolutePin
+        } else if back_role == Role::Pawn {
+            // Lining up with a pawn behind is rarely worth mentioning
+            continue;
+        } else if front_role == Role::King || piece_value(front_role) > piece_value(back_role) {
+            MotifKind::Skewer
+        } else if piece_value(back_role) > piece_value(front_role) {
+            MotifKind::RelativePin
+        } else {
+            continue;
+        };
+        motifs.push(motif(kind, front, &[sq, back]));
+    }
+
+    motifs
+}
+
+/// Lines opened by the moving piece: discovered attacks and checks
+fn discoveries(before: &Board, after: &Board, m: &Move, us: Color) -> Vec<Motif> {
+    let mut motifs = Vec::new();
+    let Some(from) = m.from() else {
+        return motifs;
+    };
+
+    let them = after.by_color(!us);
+    let sliders = after.sliders() & after.by_color(us);
+
+    for slider in sliders.without(m.to()) {
+        let Some(piece) = after.piece_at(slider) else {
+            continue;
+        };
+        let old = attacks::attacks(slider, piece, before.occupied());
+        let new = attacks::attacks(slider, piece, after.occupied()) & them;
+
+        for target in new {
+            if old.contains(target) || !attacks::between(slider, target).contains(from) {
+                continue;
+            }
+            let kind = match after.role_at(target) {
+                Some(Role::King) => MotifKind::DiscoveredCheck,
+                Some(r)
+                    if piece_value(r) > piece_value(piece.role)
+                        || after.attacks_to(target, !us, after.occupied()).is_empty() =>
+                {
+                    MotifKind::DiscoveredAttack
+                }
+                _ => continue,
+            };
+            motifs.push(motif(kind, slider, &[target]));
+        }
+    }
+
+    motifs
+}
+
+/// Enemy pieces that are the only defender of two or more attacked pieces
+fn overloaded_defenders(board: &Board, us: Color) -> Vec<Motif> {
+    let them = !us;
+    let occupied = board.occupied();
+    let mut duties: Vec<(Square, Vec<Square>)> = Vec::new();
+
+    for target in board.by_color(them) & !board.kings() {
+        if board.attacks_to(target, us, occupied).is_empty() {
+            continue;
+        }
+        let Some(defender) = board.attacks_to(target, them, occupied).single_square() else {
+            continue;
+        };
+        match duties.iter_mut().find(|(d, _)| *d == defender) {
+            Some((_, targets)) => targets.push(target),
+            None => duties.push((defender, vec![target])),
+        }
+    }
+
+    duties
+        .into_iter()
+        .filter(|(_, targets)| targets.len() >= 2)
+        .map(|(defender, targets)| motif(MotifKind::OverloadedDefender, defender, &targets))
+        .collect()
+}
+
+/// The `victim` king is stuck on its back rank with no flight square while an
+/// enemy rook or queen already bears on that rank
+pub fn back_rank_weakness(board: &Board, victim: Color) -> Option<Motif> {
+    let king = board.king_of(victim)?;
+    if king.rank() != victim.backrank() {
+        return None;
+    }
+
+    let occupied = board.occupied();
+    let attacker = !victim;
+    let has_luft = attacks::king_attacks(king)
+        .into_iter()
+        .filter(|&sq| sq.rank() != victim.backrank())
+        .any(|sq| !board.by_color(victim).contains(sq) && board.attacks_to(sq, attacker, occupied).is_empty());
+    if has_luft {
+        return None;
+    }
+
+    let back_rank = Bitboard::from(victim.backrank());
+    let heavy: Vec<Square> = (board.rooks_and_queens() & board.by_color(attacker))
+        .into_iter()
+        .filter(|&sq| {
+            board
+                .piece_at(sq)
+                .map(|p| (attacks::attacks(sq, p, occupied) & back_rank).any())
+                .unwrap_or(false)
+        })
+        .collect();
+
+    if heavy.is_empty() {
+        None
+    } else {
+        Some(motif(MotifKind::BackRankWeakness, king, &heavy))
+    }
+}
+
+/// Attacked enemy pieces that no move can save: every reply (moving the
+/// piece, blocking, or taking the attacker) still leaves it en prise.
+/// `pos` must have the victim to move.
+fn trapped_pieces(pos: &Chess, us: Color) -> Vec<Motif> {
+    let board = pos.board();
+    let them = !us;
+    let moves = pos.legal_moves();
+    let mut motifs = Vec::new();
+
+    for sq in board.by_color(them) & !board.pawns() & !board.kings() {
+        let Some(role) = board.role_at(sq) else {
+            continue;
+        };
+        if is_safe(board, sq, role, them) {
+            continue;
+        }
+        if !moves.iter().any(|mv| mv.from() == Some(sq)) {
+            // Immobile pieces (e.g. pinned) are handled by the pin motifs
+            continue;
+        }
+
+        let trapped = moves.iter().all(|mv| {
+            let mut test = pos.clone();
+            test.play_unchecked(mv);
+            let square = if mv.from() == Some(sq) { mv.to() } else { sq };
+            !is_safe(test.board(), square, role, them)
+        });
+        if trapped {
+            let attackers: Vec<Square> = board.attacks_to(sq, us, board.occupied()).into_iter().collect();
+            motifs.push(motif(MotifKind::TrappedPiece, sq, &attackers));
+        }
+    }
+
+    motifs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::{fen::Fen, uci::UciMove, CastlingMode};
+
+    fn motifs(fen: &str, uci: &str) -> Vec<(MotifKind, String)> {
+        let pos: Chess = fen
+            .parse::<Fen>()
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let m = uci.parse::<UciMove>().unwrap().to_move(&pos).unwrap();
+        detect_motifs(&pos, &m).into_iter().map(|m| (m.kind, m.square)).collect()
+    }
+
+    fn has(fen: &str, uci: &str, kind: MotifKind, square: &str) -> bool {
+        motifs(fen, uci).contains(&(kind, square.to_string()))
+    }
+
+    fn has_kind(fen: &str, uci: &str, kind: MotifKind) -> bool {
+        motifs(fen, uci).iter().any(|(k, _)| *k == kind)
+    }
+
+    #[test]
+    fn fork() {
+        assert!(has("r3k3/8/8/1N6/8/8/8/4K3 w - - 0 1", "b5c7", MotifKind::Fork, "c7"));
+        // The forking knight is simply taken
+        assert!(!has_kind("r2qk3/8/8/1N6/8/8/8/4K3 w - - 0 1", "b5c7", MotifKind::Fork));
+    }
+
+    #[test]
+    fn absolute_pin() {
+        assert!(has("4k3/8/2n5/8/8/8/8/4KB2 w - - 0 1", "f1b5", MotifKind::AbsolutePin, "c6"));
+        assert!(!has_kind("8/8/2n5/8/8/8/8/4KBk1 w - - 0 1", "f1b5", MotifKind::AbsolutePin));
+    }
+
+    #[test]
+    fn relative_pin() {
+        assert!(has("3qk3/8/5n2/8/8/8/8/2B1K3 w - - 0 1", "c1g5", MotifKind::RelativePin, "f6"));
+        assert!(!has_kind("4k3/8/5n2/8/8/8/8/2B1K3 w - - 0 1", "c1g5", MotifKind::RelativePin));
+    }
+
+    #[test]
+    fn skewer() {
+        assert!(has("8/8/8/4k2q/8/8/8/R3K3 w - - 0 1", "a1a5", MotifKind::Skewer, "e5"));
+        // Only a pawn behind the king
+        assert!(!has_kind("8/8/8/4k2p/8/8/8/R3K3 w - - 0 1", "a1a5", MotifKind::Skewer));
+    }
+
+    #[test]
+    fn discovered_attack() {
+        assert!(has("4q1k1/8/8/8/4N3/8/8/4RK2 w - - 0 1", "e4c3", MotifKind::DiscoveredAttack, "e1"));
+        // The rook now hits a defended pawn
+        assert!(!has_kind("6k1/8/5p2/4p3/4N3/8/8/4RK2 w - - 0 1", "e4c3", MotifKind::DiscoveredAttack));
+    }
+
+    #[test]
+    fn discovered_check() {
+        assert!(has("4k3/8/8/8/4N3/8/8/4RK2 w - - 0 1", "e4c3", MotifKind::DiscoveredCheck, "e1"));
+        assert!(!has_kind("4k3/8/8/8/4N3/8/8/4RK2 w - - 0 1", "e1d1", MotifKind::DiscoveredCheck));
+    }
+
+    #[test]
+    fn overloaded_defender() {
+        assert!(has("6k1/3q4/2n1b3/8/8/8/8/2R2RK1 w - - 0 1", "f1e1", MotifKind::OverloadedDefender, "d7"));
+        // Already overloaded before the move
+        assert!(!has_kind("6k1/3q4/2n1b3/8/8/8/8/2R1R1K1 w - - 0 1", "g1h1", MotifKind::OverloadedDefender));
+    }
+
+    #[test]
+    fn back_rank_weakness() {
+        assert!(has("6k1/p4ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", "a1d1", MotifKind::BackRankWeakness, "g8"));
+        // The king has luft on h7
+        assert!(!has_kind("6k1/p4pp1/7p/8/8/8/5PPP/R5K1 w - - 0 1", "a1d1", MotifKind::BackRankWeakness));
+    }
+
+    #[test]
+    fn trapped_piece() {
+        let fen = "r1bqk2r/Bppp1ppp/2n2n2/4p3/4P3/2N5/PPPP1PPP/R2QKBNR b KQkq - 0 5";
+        assert!(has(fen, "b7b6", MotifKind::TrappedPiece, "a7"));
+        assert!(!has_kind(fen, "h7h6", MotifKind::TrappedPiece));
+    }
+
+    #[test]
+    fn mate_is_not_a_fork_or_skewer() {
+        // Opera Game, 17.Rd8#
+        let motifs = motifs("1n2kb1r/p4ppp/4q3/4p1B1/4P3/8/PPP2PPP/2KR4 w k - 0 17", "d1d8");
+        assert!(!motifs.iter().any(|(k, _)| matches!(k, MotifKind::Fork | MotifKind::Skewer)));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::analysis;
+use crate::engine::{self, Limits};
+use crate::models::{Threat, ThreatKind};
+use crate::see;
+use crate::tactics;
+use shakmaty::{san::SanPlus, Chess, Move, Position};
+
+/// Material (centipawns) a capture must win to count as a threat
+const THREAT_MATERIAL: i32 = 100;
+/// How much better than the current evaluation a tactic must be
+const TACTIC_GAIN: i32 = 150;
+/// Longest mate threat looked for
+const THREAT_MATE_MOVES: u32 = 2;
+
+/// What the opponent of the side to move would play if it could move again
+/// (a null move), most dangerous first. Empty when in check, where passing
+/// is not possible.
+pub fn detect_threats(pos: &Chess) -> Vec<Threat> {
+    let Ok(null) = pos.clone().swap_turn() else {
+        return Vec::new();
+    };
+    let mut threats = immediate_threats(pos);
+
+    // Quiet tactics (forks, pins, discoveries) the engine finds for the opponent
+    let baseline = engine::analyse(pos, Limits { depth: 1, nodes: 20_000 }, 1)
+        .first()
+        .map(|l| -l.score);
+    let limits = Limits { depth: 3, nodes: 30_000 };
+    if let (Some(baseline), Some(line)) = (baseline, engine::analyse(&null, limits, 1).into_iter().next()) {
+        let gain = line.score - baseline;
+        let first = line.best();
+        let creates_motif = !tactics::detect_motifs(&null, first).is_empty();
+        if gain >= TACTIC_GAIN
+            && creates_motif
+            && engine::mate_in(line.score).is_none()
+            && !threats.iter().any(|t| t.san == san(&null, first))
+        {
+            let moves: Vec<Move> = line.moves.iter().take(3).cloned().collect();
+            threats.push(threat(&null, ThreatKind::Tactic, &moves, gain, None));
+        }
+    }
+
+    threats
+}
+
+/// Mate and capture threats only, without an engine search
+pub fn immediate_threats(pos: &Chess) -> Vec<Threat> {
+    let Ok(null) = pos.clone().swap_turn() else {
+        return Vec::new();
+    };
+    let mut threats = Vec::new();
+
+    if let Some(line) = engine::find_mate(&null, THREAT_MATE_MOVES) {
+        threats.push(threat(&null, ThreatKind::Mate, &line, 0, Some(line.len().div_ceil(2) as u32)));
+    }
+
+    let mut captures: Vec<(Move, i32)> = null
+        .capture_moves()
+        .into_iter()
+        .map(|c| {
+            let gain = see::see(&null, &c);
+            (c, gain)
+        })
+        .filter(|(_, gain)| *gain >= THREAT_MATERIAL)
+        .collect();
+    captures.sort_by_key(|(_, gain)| -gain);
+    for (capture, gain) in captures.into_iter().take(2) {
+        if !threats.iter().any(|t| t.san == san(&null, &capture)) {
+            threats.push(threat(&null, ThreatKind::Capture, &[capture], gain, None));
+        }
+    }
+
+    threats
+}
+
+fn threat(pos: &Chess, kind: ThreatKind, line: &[Move], gain: i32, mate_in: Option<u32>) -> Threat {
+    let (from, to) = analysis::extract_move_squares(&line[0]);
+    Threat {
+        kind,
+        san: san(pos, &line[0]),
+        from,
+        to,
+        line: analysis::san_line(pos, line),
+        gain,
+        mate_in,
+    }
+}
+
+fn san(pos: &Chess, m: &Move) -> String {
+    SanPlus::from_move(pos.clone(), m).to_string()
+}
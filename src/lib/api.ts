@@ -90,6 +90,246 @@ export interface MoveAnalysis {
     best_to?: string;
     fen_before: string;
     eval_score: number;
+    motifs: Motif[];
+    missed_win: MissedWin | null;
+    threats_before: Threat[];
+    candidates: Candidate[];
+    book_alternatives: BookMove[];
+}
+
+export type MotifKind =
+    | "Fork"
+    | "AbsolutePin"
+    | "RelativePin"
+    | "Skewer"
+    | "DiscoveredAttack"
+    | "DiscoveredCheck"
+    | "OverloadedDefender"
+    | "BackRankWeakness"
+    | "TrappedPiece";
+
+export interface Motif {
+    kind: MotifKind;
+    square: string;
+    targets: string[];
+}
+
+export interface Puzzle {
+    id: number;
+    game_url: string;
+    move_index: number;
+    fen: string;
+    color: string;
+    played_move: string;
+    solution: string[];
+    solution_san: string[];
+    themes: MotifKind[];
+    mate_in: number | null;
+    rating: number;
+    attempts: number;
+    solved: number;
+}
+
+export interface PuzzleMoveResult {
+    correct: boolean;
+    solved: boolean;
+    reply: string | null;
+    reply_san: string | null;
+    expected: string | null;
+}
+
+export type ReviewKind = "puzzle" | "mistake" | "opening" | "lesson";
+export type ReviewGrade = "again" | "hard" | "good" | "easy";
+
+export interface ReviewItem {
+    id: number;
+    kind: ReviewKind;
+    source_id: string;
+    title: string;
+    fen: string;
+    solution: string[];
+    ease: number;
+    interval_days: number;
+    repetitions: number;
+    lapses: number;
+    due_at: number;
+}
+
+export interface ReviewStats {
+    total_items: number;
+    due_now: number;
+    due_today: number;
+    reviewed_today: number;
+    failed_today: number;
+}
+
+export type Side = "white" | "black";
+
+export interface RepertoireMove {
+    id: number;
+    color: Side;
+    fen: string;
+    san: string;
+    uci: string;
+    fen_after: string;
+}
+
+export interface RepertoireNode {
+    fen: string;
+    note?: string;
+    user_to_move: boolean;
+    moves: RepertoireMove[];
+}
+
+export interface RepertoireImport {
+    moves_added: number;
+    notes_added: number;
+}
+
+export interface ExplorerFilter {
+    username?: string;
+    color?: Side; // the fetched account's side
+    time_class?: string;
+    since?: number; // game end time, unix seconds
+    until?: number;
+}
+
+export interface ExplorerGame {
+    url: string;
+    white: string;
+    black: string;
+    end_time?: number;
+}
+
+export interface ExplorerMove {
+    san: string;
+    uci: string;
+    games: number;
+    white_wins: number;
+    draws: number;
+    black_wins: number;
+    score: number; // percent for the side that played the move
+    last_game: ExplorerGame;
+}
+
+export interface ExplorerPosition {
+    fen: string;
+    games: number;
+    moves: ExplorerMove[];
+}
+
+export type OpeningGrouping = "eco" | "family";
+
+export interface OpeningStats {
+    key: string; // ECO code or family name
+    name: string;
+    color: Side;
+    games: number;
+    wins: number;
+    draws: number;
+    losses: number;
+    score: number; // percent, a draw counts half
+    analyzed: number;
+    avg_accuracy?: number;
+    avg_opponent_rating?: number;
+    avg_book_exit_eval?: number; // pawns, positive = good for the user
+}
+
+export type DrillSource = "repertoire" | "catalog";
+
+export interface DrillStats {
+    moves_played: number;
+    moves_correct: number;
+    completions: number;
+    last_drilled?: number; // unix seconds
+}
+
+export interface DrillLine {
+    id: string;
+    source: DrillSource;
+    name: string;
+    color: Side; // the side the user plays
+    moves: string[];
+    stats: DrillStats;
+}
+
+export interface DrillStep {
+    correct: boolean;
+    finished: boolean;
+    fen: string; // position the user moves from next
+    reply?: string; // the app's answer, UCI
+    reply_san?: string;
+    expected: string[];
+    expected_uci: string[];
+}
+
+export type DeviationKind = "UserError" | "OpponentNovelty";
+
+export interface RepertoireDeviation {
+    move_index: number;
+    move_number: number;
+    color: Side; // side that played the move
+    fen: string;
+    expected: string[]; // main line first
+    played: string;
+    kind: DeviationKind;
+}
+
+export interface ForgottenLine {
+    color: Side;
+    fen: string;
+    line: string[];
+    expected: string[];
+    played: string; // most recent wrong move
+    times: number;
+    last_game_url: string;
+    last_date: string;
+}
+
+export interface BookMove {
+    san: string;
+    uci: string;
+    from: string;
+    to: string;
+    weight: number;
+    share: number; // percent of the position's total weight
+}
+
+export interface Candidate {
+    san: string;
+    from: string;
+    to: string;
+    eval: number;
+    mate_in: number | null;
+    pv: string[];
+}
+
+export type ThreatKind = "Mate" | "Capture" | "Tactic";
+
+export interface Threat {
+    kind: ThreatKind;
+    san: string;
+    from: string;
+    to: string;
+    line: string[];
+    gain: number;
+    mate_in: number | null;
+}
+
+export type MissedWinKind = "Mate" | "Material";
+
+export interface MissedWin {
+    kind: MissedWinKind;
+    line: string[];
+    mate_in: number | null;
+    gain: number;
+}
+
+export interface HangingPiece {
+    square: string;
+    role: string;
+    color: string;
+    loss: number;
 }
 
 export interface GameSummary {
@@ -103,6 +343,8 @@ export interface GameSummary {
     blunders: number;
     accuracy: number;
     opening_name?: string;
+    missed_punishments: number;
+    eco?: string;
 }
 
 export interface GameAnalysis {
@@ -117,8 +359,35 @@ export interface GameAnalysis {
     moves: MoveAnalysis[];
     summary: GameSummary;
     key_moments: KeyMoment[];
+    missed_punishments: MissedPunishment[];
+    repertoire_deviation?: RepertoireDeviation;
+    comment_style: CoachStyle;
+    format_version: number; // analyses of an older format are redone
+}
+
+export interface MissedPunishment {
+    move_index: number;
+    move_number: number;
+    color: string;
+    error: string;
+    played: string;
+    punishment: string;
+    eval_before_error: number;
+    eval_available: number;
+    eval_after: number;
 }
 
+export type Severity = "critical" | "major" | "notable";
+
+export type MomentReason =
+    | "MissedMate"
+    | "MissedWin"
+    | "Decisive"
+    | "Swing"
+    | "OnlyMoveMissed"
+    | "OnlyMoveFound"
+    | "Brilliant";
+
 export interface KeyMoment {
     move_index: number;
     move_number: number;
@@ -126,17 +395,63 @@ export interface KeyMoment {
     color: string;
     classification: string;
     description: string;
-    severity: string;
+    severity: Severity;
+    reason: MomentReason;
+}
+
+// Stored in the "coach_language" and "coach_level" settings
+export type CoachLanguage = "en" | "el" | "de" | "es";
+export type CoachLevel = "beginner" | "intermediate" | "advanced";
+
+export interface CoachStyle {
+    language: CoachLanguage;
+    level: CoachLevel;
+}
+
+export interface GameReport {
+    opening: string;
+    middlegame_turning_point: string;
+    endgame: string;
+    takeaways: string[];
+    style: CoachStyle;
+}
+
+export interface CoachComment {
+    move_index: number;
+    comment: string;
 }
 
+export type OpeningCategory = "KingPawn" | "QueenPawn" | "Flank" | "Indian" | "SemiOpen";
+
 export interface Opening {
+    id: string;
+    parent?: string; // id of the opening this is a variation of
     eco: string;
     name: string;
     pgn: string;
     moves: string[];
-    category: string;
+    category: OpeningCategory;
     description: string;
     key_ideas: string[];
+    plans: string[];
+}
+
+export interface OpeningNode {
+    opening: Opening;
+    children: OpeningNode[];
+}
+
+export interface NamedOpening {
+    eco: string;
+    name: string;
+    pgn: string;
+    moves: string[];
+    details?: Opening; // catalog entry, when the line is in the catalog
+}
+
+export interface IdentifiedOpening {
+    opening: NamedOpening;
+    exact: boolean; // the line ends in the position rather than before it
 }
 
 export interface LessonSection {
@@ -205,10 +520,42 @@ export async function analyzeGame(
     });
 }
 
-export async function getOpenings(category?: string): Promise<Opening[]> {
+export async function getBookMoves(fen: string): Promise<BookMove[]> {
+    return invoke("get_book_moves", { fen });
+}
+
+export async function getHangingPieces(fen: string): Promise<HangingPiece[]> {
+    return invoke("get_hanging_pieces", { fen });
+}
+
+export async function getThreats(fen: string): Promise<Threat[]> {
+    return invoke("get_threats", { fen });
+}
+
+export async function getOpenings(category?: OpeningCategory): Promise<Opening[]> {
     return invoke("get_openings", { category: category || null });
 }
 
+export async function getOpeningTree(category?: OpeningCategory): Promise<OpeningNode[]> {
+    return invoke("get_opening_tree", { category: category || null });
+}
+
+export async function getOpeningVariations(id: string): Promise<Opening[]> {
+    return invoke("get_opening_variations", { id });
+}
+
+export async function identifyOpening(fen: string): Promise<IdentifiedOpening | null> {
+    return invoke("identify_opening", { fen });
+}
+
+export async function getOpeningByEco(eco: string): Promise<NamedOpening | null> {
+    return invoke("get_opening_by_eco", { eco });
+}
+
+export async function searchOpenings(query: string, limit = 20): Promise<NamedOpening[]> {
+    return invoke("search_openings", { query, limit });
+}
+
 export async function getLessons(category?: string): Promise<Lesson[]> {
     return invoke("get_lessons", { category: category || null });
 }
@@ -227,9 +574,7 @@ export async function getCoachComment(
     fen: string,
     playedMove: string,
     bestMove: string | null,
-    classification: string,
-    color: string,
-    moveNumber: number
+    classification: string
 ): Promise<string> {
     return invoke("get_coach_comment", {
         gameUrl,
@@ -238,7 +583,121 @@ export async function getCoachComment(
         playedMove,
         bestMove,
         classification,
-        color,
-        moveNumber,
     });
 }
+
+export async function regenerateCoachComment(
+    gameUrl: string,
+    moveIndex: number,
+    fen: string,
+    playedMove: string,
+    bestMove: string | null,
+    classification: string
+): Promise<string> {
+    return invoke("regenerate_coach_comment", {
+        gameUrl,
+        moveIndex,
+        fen,
+        playedMove,
+        bestMove,
+        classification,
+    });
+}
+
+export async function purgeCoachCache(gameUrl?: string): Promise<number> {
+    return invoke("purge_coach_cache", { gameUrl: gameUrl || null });
+}
+
+export async function getGameReport(gameUrl: string): Promise<GameReport> {
+    return invoke("get_game_report", { gameUrl });
+}
+
+export async function getKeyMomentComments(gameUrl: string): Promise<CoachComment[]> {
+    return invoke("get_key_moment_comments", { gameUrl });
+}
+
+export async function generatePuzzles(gameUrl: string): Promise<Puzzle[]> {
+    return invoke("generate_puzzles", { gameUrl });
+}
+
+export async function getPuzzles(gameUrl: string | null = null, limit: number = 50): Promise<Puzzle[]> {
+    return invoke("get_puzzles", { gameUrl, limit });
+}
+
+export async function checkPuzzleMove(puzzleId: number, step: number, uci: string): Promise<PuzzleMoveResult> {
+    return invoke("check_puzzle_move", { puzzleId, step, uci });
+}
+
+export async function getDueReviews(limit: number = 20): Promise<ReviewItem[]> {
+    return invoke("get_due_reviews", { limit });
+}
+
+export async function submitReview(itemId: number, grade: ReviewGrade): Promise<ReviewItem> {
+    return invoke("submit_review", { itemId, grade });
+}
+
+export async function addReviewItem(
+    kind: ReviewKind,
+    sourceId: string,
+    title: string,
+    fen: string,
+    solution: string[]
+): Promise<number | null> {
+    return invoke("add_review_item", { kind, sourceId, title, fen, solution });
+}
+
+export async function getReviewStats(): Promise<ReviewStats> {
+    return invoke("get_review_stats");
+}
+
+export async function addRepertoireLine(color: Side, moves: string[]): Promise<number> {
+    return invoke("add_repertoire_line", { color, moves });
+}
+
+export async function removeRepertoireLine(color: Side, moves: string[]): Promise<number> {
+    return invoke("remove_repertoire_line", { color, moves });
+}
+
+export async function getRepertoireNode(color: Side, fen: string): Promise<RepertoireNode> {
+    return invoke("get_repertoire_node", { color, fen });
+}
+
+export async function annotateRepertoirePosition(color: Side, fen: string, note: string): Promise<void> {
+    return invoke("annotate_repertoire_position", { color, fen, note });
+}
+
+export async function importRepertoirePgn(color: Side, pgn: string): Promise<RepertoireImport> {
+    return invoke("import_repertoire_pgn", { color, pgn });
+}
+
+export async function exportRepertoirePgn(color: Side): Promise<string> {
+    return invoke("export_repertoire_pgn", { color });
+}
+
+export async function getForgottenLines(limit: number): Promise<ForgottenLine[]> {
+    return invoke("get_forgotten_lines", { limit });
+}
+
+export async function getDrillLines(source: DrillSource, color?: Side): Promise<DrillLine[]> {
+    return invoke("get_drill_lines", { source, color });
+}
+
+export async function startDrill(lineId: string): Promise<DrillStep> {
+    return invoke("start_drill", { lineId });
+}
+
+export async function checkDrillMove(lineId: string, fen: string, uci: string): Promise<DrillStep> {
+    return invoke("check_drill_move", { lineId, fen, uci });
+}
+
+export async function getOpeningStats(
+    username: string,
+    grouping: OpeningGrouping,
+    color?: Side
+): Promise<OpeningStats[]> {
+    return invoke("get_opening_stats", { username, grouping, color });
+}
+
+export async function explorePosition(fen: string, filter: ExplorerFilter = {}): Promise<ExplorerPosition> {
+    return invoke("explore_position", { fen, filter });
+}
@@ -262,9 +262,7 @@ export function GameAnalysis() {
                 move.fen_before,
                 move.san,
                 move.best_move_san || null,
-                move.classification,
-                move.color,
-                move.move_number
+                move.classification
             );
             setCoachComment(comment);
             setCoachDialogOpen(true);
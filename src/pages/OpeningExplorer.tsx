@@ -20,14 +20,14 @@ import {
     Compass,
     Loader2,
 } from "lucide-react";
-import { getOpenings, type Opening } from "@/lib/api";
+import { getOpenings, type Opening, type OpeningCategory } from "@/lib/api";
 
-const categoryInfo: Record<string, { label: string; icon: React.ReactNode; color: string; value: string }> = {
-    KingPawn: { label: "King's Pawn (e4)", icon: <Swords className="w-4 h-4" />, color: "text-red-400", value: "king_pawn" },
-    QueenPawn: { label: "Queen's Pawn (d4)", icon: <Shield className="w-4 h-4" />, color: "text-blue-400", value: "queen_pawn" },
-    SemiOpen: { label: "Semi-Open", icon: <Compass className="w-4 h-4" />, color: "text-amber-400", value: "semi_open" },
-    Indian: { label: "Indian Systems", icon: <BookOpen className="w-4 h-4" />, color: "text-emerald-400", value: "indian" },
-    Flank: { label: "Flank Openings", icon: <ChevronRight className="w-4 h-4" />, color: "text-violet-400", value: "flank" },
+const categoryInfo: Record<OpeningCategory, { label: string; icon: React.ReactNode; color: string }> = {
+    KingPawn: { label: "King's Pawn (e4)", icon: <Swords className="w-4 h-4" />, color: "text-red-400" },
+    QueenPawn: { label: "Queen's Pawn (d4)", icon: <Shield className="w-4 h-4" />, color: "text-blue-400" },
+    SemiOpen: { label: "Semi-Open", icon: <Compass className="w-4 h-4" />, color: "text-amber-400" },
+    Indian: { label: "Indian Systems", icon: <BookOpen className="w-4 h-4" />, color: "text-emerald-400" },
+    Flank: { label: "Flank Openings", icon: <ChevronRight className="w-4 h-4" />, color: "text-violet-400" },
 };
 
 export function OpeningExplorer() {
@@ -44,7 +44,7 @@ export function OpeningExplorer() {
     async function loadOpenings() {
         setLoading(true);
         try {
-            const category = activeCategory === "all" ? undefined : activeCategory;
+            const category = activeCategory === "all" ? undefined : (activeCategory as OpeningCategory);
             const o = await getOpenings(category);
             setOpenings(o);
         } catch {
@@ -64,12 +64,12 @@ export function OpeningExplorer() {
         return "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
     }
 
-    function getCategoryColor(cat: string): string {
+    function getCategoryColor(cat: OpeningCategory): string {
         const info = categoryInfo[cat];
         return info?.color || "text-foreground";
     }
 
-    function getCategoryLabel(cat: string): string {
+    function getCategoryLabel(cat: OpeningCategory): string {
         const info = categoryInfo[cat];
         return info?.label || cat;
     }
@@ -91,7 +91,7 @@ export function OpeningExplorer() {
                     {Object.entries(categoryInfo).map(([key, info]) => (
                         <TabsTrigger
                             key={key}
-                            value={info.value}
+                            value={key}
                             className="data-[state=active]:bg-white/10 gap-1.5"
                         >
                             {info.icon}